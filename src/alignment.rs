@@ -0,0 +1,360 @@
+//! Pointing correction computed from stars centered by hand, with the matrix method of Toshimi Taki.
+//!
+//! Each alignment star pairs the catalog position of a star with the position the mount reports
+//! once the star is centered. The model is the linear map between the direction cosines of both,
+//! in the equatorial frame: it absorbs the polar misalignment and the offsets of the encoders,
+//! as long as the mount keeps tracking between the alignment and the gotos.
+//!
+//! The window computes the model, and `Aligned` applies it to every command reaching the backend,
+//! wherever it comes from (the window or Stellarium):
+//!
+//! ```ignore
+//! let model = SharedModel::default();
+//! let mut backend = Aligned::new(Simulator::new(SimulatorConfig::default()), model.clone());
+//! thread::spawn(move || backends::run(&mut backend, commands, events, Duration::from_secs(1)));
+//! make_window(sender, receiver, model);
+//! ```
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    backends::Backend,
+    data::{Angle, AngleKind, Sign, Unit, MountStatus, separation},
+    events::UserEvent
+};
+
+use Sign::{Positive as N, Negative as S};
+
+
+/// A star usable for the alignment, with its J2000 coordinates
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BrightStar {
+    pub name: &'static str,
+    pub right_asc: Angle,
+    pub decl: Angle
+}
+
+const fn star(name: &'static str, right_asc: (u16, u8, f64), decl: (Sign, u16, u8, f64)) -> BrightStar {
+    BrightStar {
+        name,
+        right_asc: Angle(Sign::Positive, right_asc.0, right_asc.1, right_asc.2),
+        decl: Angle(decl.0, decl.1, decl.2, decl.3)
+    }
+}
+
+/// Brightest stars of both hemispheres, by right ascension
+pub static BRIGHT_STARS: [BrightStar; 22] = [
+    star("Alphératz", (0, 8, 23.3), (N, 29, 5, 26.0)),
+    star("Achernar", (1, 37, 42.8), (S, 57, 14, 12.0)),
+    star("Polaris", (2, 31, 49.1), (N, 89, 15, 51.0)),
+    star("Aldébaran", (4, 35, 55.2), (N, 16, 30, 33.0)),
+    star("Rigel", (5, 14, 32.3), (S, 8, 12, 6.0)),
+    star("Capella", (5, 16, 41.4), (N, 45, 59, 53.0)),
+    star("Bételgeuse", (5, 55, 10.3), (N, 7, 24, 25.0)),
+    star("Canopus", (6, 23, 57.1), (S, 52, 41, 45.0)),
+    star("Sirius", (6, 45, 8.9), (S, 16, 42, 58.0)),
+    star("Castor", (7, 34, 36.0), (N, 31, 53, 18.0)),
+    star("Procyon", (7, 39, 18.1), (N, 5, 13, 30.0)),
+    star("Pollux", (7, 45, 18.9), (N, 28, 1, 34.0)),
+    star("Régulus", (10, 8, 22.3), (N, 11, 58, 2.0)),
+    star("Dubhe", (11, 3, 43.7), (N, 61, 45, 3.0)),
+    star("Acrux", (12, 26, 35.9), (S, 63, 5, 57.0)),
+    star("Spica", (13, 25, 11.6), (S, 11, 9, 41.0)),
+    star("Arcturus", (14, 15, 39.7), (N, 19, 10, 57.0)),
+    star("Antarès", (16, 29, 24.4), (S, 26, 25, 55.0)),
+    star("Véga", (18, 36, 56.3), (N, 38, 47, 1.0)),
+    star("Altaïr", (19, 50, 47.0), (N, 8, 52, 6.0)),
+    star("Deneb", (20, 41, 25.9), (N, 45, 16, 49.0)),
+    star("Fomalhaut", (22, 57, 39.0), (S, 29, 37, 20.0))
+];
+
+
+/// A star centered during the alignment. Right ascensions are in hours, declinations in degrees.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AlignmentStar {
+    /// catalog position
+    pub sky: (Angle, Angle),
+    /// position reported by the mount once the star is centered
+    pub mount: (Angle, Angle)
+}
+
+type Vector = [f64; 3];
+type Matrix = [[f64; 3]; 3];
+
+/// Correction from the sky to the mount coordinates, and back
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AlignmentModel {
+    to_mount: Matrix,
+    to_sky: Matrix,
+    /// number of stars it was computed from
+    pub stars: usize
+}
+
+impl AlignmentModel {
+    /// Builds the model from two or three stars. None if there are not as many, or if they are
+    /// too close to each other (or to a great circle, for three stars) to tell the axes apart.
+    pub fn from_stars(stars: &[AlignmentStar]) -> Option<AlignmentModel> {
+        let (sky, mount) = match stars {
+            [first, second] => {
+                let sky = [to_vector(first.sky), to_vector(second.sky)];
+                let mount = [to_vector(first.mount), to_vector(second.mount)];
+                // the third vector is orthogonal to the first two, in both frames
+                (
+                    [sky[0], sky[1], normalize(cross(sky[0], sky[1]))?],
+                    [mount[0], mount[1], normalize(cross(mount[0], mount[1]))?]
+                )
+            },
+            [first, second, third] => (
+                [to_vector(first.sky), to_vector(second.sky), to_vector(third.sky)],
+                [to_vector(first.mount), to_vector(second.mount), to_vector(third.mount)]
+            ),
+            _ => return None
+        };
+
+        // the vectors are the columns
+        let (sky, mount) = (transpose(sky), transpose(mount));
+        let to_mount = multiply(mount, inverse(sky)?);
+
+        Some(AlignmentModel {
+            to_mount,
+            to_sky: inverse(to_mount)?,
+            stars: stars.len()
+        })
+    }
+
+    /// Coordinates to send to the mount to point at `right_asc` and `decl`
+    pub fn to_mount(&self, right_asc: Angle, decl: Angle) -> (Angle, Angle) {
+        from_vector(apply(self.to_mount, to_vector((right_asc, decl))))
+    }
+
+    /// Coordinates of the sky at which the mount points, when it reports `right_asc` and `decl`
+    pub fn to_sky(&self, right_asc: Angle, decl: Angle) -> (Angle, Angle) {
+        from_vector(apply(self.to_sky, to_vector((right_asc, decl))))
+    }
+}
+
+/// Alignment model shared between the window, which computes it, and `Aligned`, which applies it
+#[derive(Clone, Default)]
+pub struct SharedModel(Arc<Mutex<Option<AlignmentModel>>>);
+
+impl SharedModel {
+    /// Replaces the model, None to stop correcting the pointing
+    pub fn set(&self, model: Option<AlignmentModel>) {
+        *self.0.lock().unwrap() = model;
+    }
+
+    pub fn get(&self) -> Option<AlignmentModel> {
+        *self.0.lock().unwrap()
+    }
+}
+
+
+/// Backend correcting the pointing of another one with the shared model: the gotos and the syncs
+/// are given the mount coordinates of their target, and the positions reported are those of the sky.
+pub struct Aligned<B> {
+    backend: B,
+    model: SharedModel
+}
+
+impl<B: Backend> Aligned<B> {
+    pub fn new(backend: B, model: SharedModel) -> Self {
+        Aligned { backend, model }
+    }
+}
+
+impl<B: Backend> Backend for Aligned<B> {
+    fn handle(&mut self, event: &UserEvent) -> io::Result<()> {
+        let model = match self.model.get() {
+            Some(model) => model,
+            None => return self.backend.handle(event)
+        };
+
+        match *event {
+            UserEvent::Rotate(right_asc, decl) => {
+                let (right_asc, decl) = model.to_mount(right_asc, decl);
+                self.backend.handle(&UserEvent::Rotate(right_asc, decl))
+            },
+            UserEvent::Sync(right_asc, decl) => {
+                let (right_asc, decl) = model.to_mount(right_asc, decl);
+                self.backend.handle(&UserEvent::Sync(right_asc, decl))
+            },
+            _ => self.backend.handle(event)
+        }
+    }
+
+    /// The altitude and azimuth are those reported by the mount: the model does not know the site
+    fn status(&mut self) -> io::Result<MountStatus> {
+        let mut status = self.backend.status()?;
+        if let Some(model) = self.model.get() {
+            let (right_asc, decl) = model.to_sky(status.right_asc, status.decl);
+            status.right_asc = right_asc;
+            status.decl = decl;
+        }
+        Ok(status)
+    }
+}
+
+
+/// Largest difference between the separations of two stars in the sky and on the mount.
+/// A large one reveals a star centered badly, or mistaken for another.
+pub fn separation_error(stars: &[AlignmentStar]) -> Angle {
+    let mut error = 0f64;
+    for (i, first) in stars.iter().enumerate() {
+        for second in &stars[i + 1..] {
+            let sky = separation(first.sky, second.sky, Unit::Hours).to_decimal();
+            let mount = separation(first.mount, second.mount, Unit::Hours).to_decimal();
+            error = error.max((sky - mount).abs());
+        }
+    }
+    Angle::from_decimal(error)
+}
+
+
+/// Below this, vectors are taken as aligned and matrices as not invertible
+/// (about 0.06° between two stars)
+const EPSILON: f64 = 1e-3;
+
+fn to_vector((right_asc, decl): (Angle, Angle)) -> Vector {
+    let (right_asc, decl) = (right_asc.to_radians(Unit::Hours), decl.to_radians(Unit::Degrees));
+    [decl.cos() * right_asc.cos(), decl.cos() * right_asc.sin(), decl.sin()]
+}
+
+fn from_vector(v: Vector) -> (Angle, Angle) {
+    // the matrix of three stars does not keep the lengths exactly
+    let norm = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    let right_asc = AngleKind::RightAscension.normalize(Angle::from_radians(v[1].atan2(v[0]), Unit::Hours));
+    let decl = Angle::from_radians((v[2] / norm).clamp(-1.0, 1.0).asin(), Unit::Degrees);
+    (right_asc, decl)
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(v: Vector) -> Option<Vector> {
+    let norm = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if norm < EPSILON {
+        return None;
+    }
+    Some([v[0] / norm, v[1] / norm, v[2] / norm])
+}
+
+fn transpose(m: Matrix) -> Matrix {
+    let mut t = [[0.0; 3]; 3];
+    for (i, row) in m.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            t[j][i] = *value;
+        }
+    }
+    t
+}
+
+fn multiply(a: Matrix, b: Matrix) -> Matrix {
+    let mut product = [[0.0; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+fn apply(m: Matrix, v: Vector) -> Vector {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2]
+    ]
+}
+
+/// Inverse through the cofactors, None if the matrix is (nearly) singular
+fn inverse(m: Matrix) -> Option<Matrix> {
+    let cofactor = |i: usize, j: usize| {
+        let (r1, r2) = ((i + 1) % 3, (i + 2) % 3);
+        let (c1, c2) = ((j + 1) % 3, (j + 2) % 3);
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+
+    let determinant = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    if determinant.abs() < EPSILON {
+        return None;
+    }
+
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // transposed cofactors
+            *value = cofactor(j, i) / determinant;
+        }
+    }
+    Some(inverse)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::backends::{Simulator, SimulatorConfig};
+
+    /// Coordinates reported by a mount whose right ascension encoder is ahead by half an hour
+    fn mount_of(right_asc: Angle, decl: Angle) -> (Angle, Angle) {
+        (AngleKind::RightAscension.normalize(right_asc + Angle::from_decimal(0.5)), decl)
+    }
+
+    fn model() -> AlignmentModel {
+        let stars: Vec<AlignmentStar> = ["Véga", "Arcturus"].iter().map(|name| {
+            let star = BRIGHT_STARS.iter().find(|star| star.name == *name).unwrap();
+            AlignmentStar { sky: (star.right_asc, star.decl), mount: mount_of(star.right_asc, star.decl) }
+        }).collect();
+        assert!(separation_error(&stars).to_decimal() < 1e-6);
+
+        AlignmentModel::from_stars(&stars).unwrap()
+    }
+
+    fn assert_close(first: (Angle, Angle), second: (Angle, Angle)) {
+        assert!(separation(first, second, Unit::Hours).to_decimal() < 1e-3, "{:?} is not {:?}", first, second);
+    }
+
+    #[test]
+    fn model_round_trip() {
+        let model = model();
+        let target = (Angle::from_decimal(3.2), Angle::from_decimal(-41.0));
+
+        let mount = model.to_mount(target.0, target.1);
+        assert_close(mount, mount_of(target.0, target.1));
+        assert_close(model.to_sky(mount.0, mount.1), target);
+        assert!(AlignmentModel::from_stars(&[AlignmentStar { sky: target, mount }; 2]).is_none());
+    }
+
+    #[test]
+    fn aligned_backend() {
+        let shared = SharedModel::default();
+        let simulator = Simulator::new(SimulatorConfig { realtime: false, ..Default::default() });
+        let mut aligned = Aligned::new(simulator, shared.clone());
+        let target = (Angle::from_decimal(3.2), Angle::from_decimal(41.0));
+
+        // without model, the commands are left as they are
+        aligned.handle(&UserEvent::Sync(target.0, target.1)).unwrap();
+        assert_close((aligned.status().unwrap().right_asc, aligned.status().unwrap().decl), target);
+
+        shared.set(Some(model()));
+        aligned.handle(&UserEvent::Sync(target.0, target.1)).unwrap();
+        let status = aligned.backend.pointing();
+        assert_close((status.right_asc, status.decl), mount_of(target.0, target.1));
+        let status = aligned.status().unwrap();
+        assert_close((status.right_asc, status.decl), target);
+
+        let target = (Angle::from_decimal(5.0), Angle::from_decimal(20.0));
+        aligned.handle(&UserEvent::Rotate(target.0, target.1)).unwrap();
+        for _ in 0..600 {
+            aligned.backend.step(Duration::from_millis(100));
+        }
+        let status = aligned.backend.pointing();
+        assert_close((status.right_asc, status.decl), mount_of(target.0, target.1));
+        let status = aligned.status().unwrap();
+        assert_close((status.right_asc, status.decl), target);
+    }
+}
@@ -0,0 +1,442 @@
+//! ASCOM Alpaca client: drives any telescope exposed through the Alpaca HTTP/JSON API.
+
+use std::io;
+
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{
+    backends::Backend,
+    data::{Angle, AngleKind, Direction, TrackingMode, ParkPosition, MountStatus},
+    events::UserEvent
+};
+
+
+/// Fastest rate of the manual moves, in degrees per second
+const MAX_MOVE_RATE: f64 = 3.0;
+
+/// Length of a sidereal second, in seconds
+const SIDEREAL_SECOND: f64 = 0.997_269_57;
+
+// identifies this client to the Alpaca server
+const CLIENT_ID: u32 = 0xbee5;
+
+// error of the properties and methods the telescope does not have
+const NOT_IMPLEMENTED: i32 = 0x400;
+
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Response<T> {
+    value: Option<T>,
+    error_number: i32,
+    error_message: String
+}
+
+impl<T> Response<T> {
+    fn check(self) -> io::Result<Option<T>> {
+        let kind = match self.error_number {
+            0 => return Ok(self.value),
+            NOT_IMPLEMENTED => io::ErrorKind::Unsupported,
+            _ => io::ErrorKind::Other
+        };
+        Err(io::Error::new(kind, format!("Alpaca error {}: {}", self.error_number, self.error_message)))
+    }
+}
+
+
+pub struct Alpaca {
+    agent: ureq::Agent,
+    /// e.g. "http://localhost:11111/api/v1/telescope/0"
+    url: String,
+    transaction: u32,
+    /// true while slewing to a park position, which is recorded as the park position of the mount once reached
+    parking: bool
+}
+
+impl Alpaca {
+    /// Client of the telescope number `device` of the server at `address` (e.g. "http://localhost:11111"),
+    /// which must be connected before it accepts the commands
+    pub fn new(address: &str, device: u32) -> Self {
+        Alpaca {
+            agent: ureq::Agent::new(),
+            url: format!("{}/api/v1/telescope/{}", address.trim_end_matches('/'), device),
+            transaction: 0,
+            parking: false
+        }
+    }
+
+    /// Connects to the telescope number `device` of the server at `address`
+    pub fn connect(address: &str, device: u32) -> io::Result<Self> {
+        let mut alpaca = Alpaca::new(address, device);
+        alpaca.put("connected", &[("Connected", "true".to_string())])?;
+        Ok(alpaca)
+    }
+
+    fn next_transaction(&mut self) -> String {
+        self.transaction = self.transaction.wrapping_add(1);
+        self.transaction.to_string()
+    }
+
+    /// Reads a property of the telescope
+    fn get<T: DeserializeOwned>(&mut self, property: &str) -> io::Result<T> {
+        let transaction = self.next_transaction();
+        let response: Response<T> = self.agent.get(&format!("{}/{}", self.url, property))
+            .query("ClientID", &CLIENT_ID.to_string())
+            .query("ClientTransactionID", &transaction)
+            .call()
+            .map_err(http_error)?
+            .into_json()?;
+
+        response.check()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("no value for {}", property)))
+    }
+
+    /// Sets a property or calls a method of the telescope
+    fn put(&mut self, method: &str, params: &[(&str, String)]) -> io::Result<()> {
+        let client = CLIENT_ID.to_string();
+        let transaction = self.next_transaction();
+
+        let mut form: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        form.push(("ClientID", &client));
+        form.push(("ClientTransactionID", &transaction));
+
+        let response: Response<serde_json::Value> = self.agent.put(&format!("{}/{}", self.url, method))
+            .send_form(&form)
+            .map_err(http_error)?
+            .into_json()?;
+
+        response.check().map(|_| ())
+    }
+
+    /// Selects the tracking rate, then enables the tracking (or disables it if `mode` is None)
+    fn track(&mut self, mode: Option<TrackingMode>) -> io::Result<()> {
+        let mode = match mode {
+            Some(mode) => mode,
+            None => return self.put("tracking", &[("Tracking", "false".to_string())])
+        };
+
+        let drive_rate = match mode {
+            TrackingMode::Lunar => 1,
+            TrackingMode::Solar => 2,
+            TrackingMode::King => 3,
+            _ => 0
+        };
+        self.put("trackingrate", &[("TrackingRate", drive_rate.to_string())])?;
+
+        // offset from the sidereal rate, in seconds of right ascension per sidereal second.
+        // The other modes reset it, else the offset of a previous custom rate would remain.
+        let offset = match mode {
+            TrackingMode::Custom(rate) => (TrackingMode::Sidereal.rate() - rate) / 15.0 * SIDEREAL_SECOND,
+            _ => 0.0
+        };
+        match self.put("rightascensionrate", &[("RightAscensionRate", offset.to_string())]) {
+            // the mounts which cannot set it have no offset to reset
+            Err(e) if e.kind() == io::ErrorKind::Unsupported && offset == 0.0 => {},
+            result => result?
+        }
+
+        self.put("tracking", &[("Tracking", "true".to_string())])
+    }
+
+    fn tracking_mode(&mut self) -> io::Result<TrackingMode> {
+        Ok(match self.get::<u8>("trackingrate")? {
+            1 => TrackingMode::Lunar,
+            2 => TrackingMode::Solar,
+            3 => TrackingMode::King,
+            _ => {
+                // only available on the mounts which can set it
+                let offset = match self.get::<f64>("rightascensionrate") {
+                    Err(e) if e.kind() == io::ErrorKind::Unsupported => 0.0,
+                    result => result?
+                };
+                if offset == 0.0 {
+                    TrackingMode::Sidereal
+                } else {
+                    TrackingMode::Custom(TrackingMode::Sidereal.rate() - offset * 15.0 / SIDEREAL_SECOND)
+                }
+            }
+        })
+    }
+
+    /// Slews to the position, which `status` makes the park position of the mount once reached
+    fn park(&mut self, position: ParkPosition) -> io::Result<()> {
+        // slewing to horizontal coordinates is only allowed while not tracking
+        self.put("tracking", &[("Tracking", "false".to_string())])?;
+        self.put("slewtoaltazasync", &[
+            ("Azimuth", position.azimuth.to_decimal().to_string()),
+            ("Altitude", position.altitude.to_decimal().to_string())
+        ])?;
+        self.parking = true;
+        Ok(())
+    }
+
+    fn move_axis(&mut self, dir: Direction, rate: f64) -> io::Result<()> {
+        // the primary axis is the right ascension (or azimuth) one
+        let (axis, sign) = match dir {
+            Direction::Up => (1, 1.0),
+            Direction::Down => (1, -1.0),
+            Direction::Left => (0, 1.0),
+            Direction::Right => (0, -1.0)
+        };
+
+        self.put("moveaxis", &[("Axis", axis.to_string()), ("Rate", (sign * rate).to_string())])
+    }
+}
+
+impl Backend for Alpaca {
+    fn handle(&mut self, event: &UserEvent) -> io::Result<()> {
+        match *event {
+            UserEvent::Rotate(right_asc, decl) => {
+                // slewing to coordinates is only allowed while tracking
+                self.put("tracking", &[("Tracking", "true".to_string())])?;
+                self.put("slewtocoordinatesasync", &[
+                    ("RightAscension", right_asc.to_decimal().to_string()),
+                    ("Declination", decl.to_decimal().to_string())
+                ])
+            },
+            UserEvent::Sync(right_asc, decl) => self.put("synctocoordinates", &[
+                ("RightAscension", right_asc.to_decimal().to_string()),
+                ("Declination", decl.to_decimal().to_string())
+            ]),
+            UserEvent::BeginMove(dir, rate) => self.move_axis(dir, rate.to_degrees_per_second(MAX_MOVE_RATE)),
+            UserEvent::EndMove(dir) => self.move_axis(dir, 0.0),
+            UserEvent::Position(longitude, latitude) => {
+                self.put("sitelongitude", &[("SiteLongitude", longitude.to_decimal().to_string())])?;
+                self.put("sitelatitude", &[("SiteLatitude", latitude.to_decimal().to_string())])
+            },
+            UserEvent::Zero | UserEvent::KeepMoving => Ok(()),
+            // also stops the moves started with MoveAxis
+            UserEvent::Abort => {
+                self.parking = false;
+                self.put("abortslew", &[])
+            },
+            UserEvent::Track(mode) => self.track(mode),
+            UserEvent::Park(position) => self.park(position),
+            UserEvent::Unpark => self.put("unpark", &[]),
+            UserEvent::FindHome => self.put("findhome", &[])
+        }
+    }
+
+    fn status(&mut self) -> io::Result<MountStatus> {
+        let slewing: bool = self.get("slewing")?;
+        if self.parking && !slewing {
+            self.parking = false;
+            self.put("setpark", &[])?;
+            self.put("park", &[])?;
+        }
+
+        Ok(MountStatus {
+            right_asc: AngleKind::RightAscension.normalize(Angle::from_decimal(self.get("rightascension")?)),
+            decl: Angle::from_decimal(self.get("declination")?),
+            altitude: Angle::from_decimal(self.get("altitude")?),
+            azimuth: Angle::from_decimal(self.get("azimuth")?),
+            tracking: self.get("tracking")?,
+            tracking_mode: match self.tracking_mode() {
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => None,
+                result => Some(result?)
+            },
+            slewing,
+            parked: self.get("atpark")?
+        })
+    }
+}
+
+
+fn http_error(e: ureq::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        backends::fixtures,
+        data::{Sign, SlewRate}
+    };
+
+    /// A request received by the mock server, without the client identifiers
+    #[derive(Debug, PartialEq)]
+    struct Request {
+        method: String,
+        property: String,
+        params: Vec<(String, String)>
+    }
+
+    fn put(property: &str, params: &[(&str, &str)]) -> Request {
+        Request {
+            method: "PUT".to_string(),
+            property: property.to_string(),
+            params: params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        }
+    }
+
+    /// Starts an Alpaca server answering the properties of `values` (JSON values, or "!" and an error number),
+    /// and the other ones with the "not implemented" error. Returns its address and the requests it receives.
+    fn serve(values: &'static [(&'static str, &'static str)]) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let received = requests.clone();
+        let address = fixtures::serve(move |stream| {
+            let mut stream = BufReader::new(stream);
+
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            let mut words = line.split_whitespace();
+            let method = words.next().unwrap().to_string();
+            let path = words.next().unwrap().to_string();
+
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                stream.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).unwrap();
+
+            let (path, query) = path.split_once('?').unwrap_or((&path, ""));
+            let form = if method == "PUT" { String::from_utf8(body).unwrap() } else { query.to_string() };
+            let params: Vec<(String, String)> = form.split('&')
+                .filter_map(|param| param.split_once('='))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            assert!(params.contains(&("ClientID".to_string(), CLIENT_ID.to_string())));
+
+            let property = path.rsplit('/').next().unwrap().to_string();
+            let body = match values.iter().find(|(name, _)| *name == property) {
+                _ if method == "PUT" => r#"{"ErrorNumber":0,"ErrorMessage":""}"#.to_string(),
+                Some((_, value)) if value.starts_with('!') => format!(r#"{{"ErrorNumber":{},"ErrorMessage":"failed"}}"#, &value[1..]),
+                Some((_, value)) => format!(r#"{{"Value":{},"ErrorNumber":0,"ErrorMessage":""}}"#, value),
+                None => r#"{"ErrorNumber":1024,"ErrorMessage":"not implemented"}"#.to_string()
+            };
+            received.lock().unwrap().push(Request {
+                method,
+                property,
+                params: params.into_iter().filter(|(k, _)| !k.starts_with("Client")).collect()
+            });
+
+            let mut stream = stream.into_inner();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body).unwrap();
+        });
+
+        (format!("http://{}", address), requests)
+    }
+
+    fn take(requests: &Arc<Mutex<Vec<Request>>>) -> Vec<Request> {
+        std::mem::take(&mut *requests.lock().unwrap())
+    }
+
+    static STATUS: [(&str, &str); 8] = [
+        ("slewing", "false"),
+        ("rightascension", "25.5"),
+        ("declination", "-45.25"),
+        ("altitude", "30.5"),
+        ("azimuth", "180"),
+        ("tracking", "true"),
+        ("trackingrate", "2"),
+        ("atpark", "false")
+    ];
+
+    #[test]
+    fn commands() {
+        let (address, requests) = serve(&STATUS);
+        let mut alpaca = Alpaca::connect(&address, 0).unwrap();
+        assert_eq!(take(&requests), [put("connected", &[("Connected", "true")])]);
+
+        alpaca.handle(&UserEvent::Rotate(Angle(Sign::Positive, 12, 30, 0.0), Angle(Sign::Negative, 45, 15, 0.0))).unwrap();
+        alpaca.handle(&UserEvent::Sync(Angle(Sign::Positive, 6, 45, 0.0), Angle(Sign::Positive, 0, 30, 0.0))).unwrap();
+        assert_eq!(take(&requests), [
+            put("tracking", &[("Tracking", "true")]),
+            put("slewtocoordinatesasync", &[("RightAscension", "12.5"), ("Declination", "-45.25")]),
+            put("synctocoordinates", &[("RightAscension", "6.75"), ("Declination", "0.5")])
+        ]);
+
+        alpaca.handle(&UserEvent::BeginMove(Direction::Up, SlewRate::Max)).unwrap();
+        alpaca.handle(&UserEvent::EndMove(Direction::Up)).unwrap();
+        alpaca.handle(&UserEvent::BeginMove(Direction::Right, SlewRate::Max)).unwrap();
+        alpaca.handle(&UserEvent::Abort).unwrap();
+        assert_eq!(take(&requests), [
+            put("moveaxis", &[("Axis", "1"), ("Rate", "3")]),
+            put("moveaxis", &[("Axis", "1"), ("Rate", "0")]),
+            put("moveaxis", &[("Axis", "0"), ("Rate", "-3")]),
+            put("abortslew", &[])
+        ]);
+
+        alpaca.handle(&UserEvent::Position(Angle(Sign::Negative, 70, 30, 0.0), Angle(Sign::Positive, 48, 45, 0.0))).unwrap();
+        alpaca.handle(&UserEvent::Track(Some(TrackingMode::King))).unwrap();
+        alpaca.handle(&UserEvent::Track(None)).unwrap();
+        assert_eq!(take(&requests), [
+            put("sitelongitude", &[("SiteLongitude", "-70.5")]),
+            put("sitelatitude", &[("SiteLatitude", "48.75")]),
+            put("trackingrate", &[("TrackingRate", "3")]),
+            put("rightascensionrate", &[("RightAscensionRate", "0")]),
+            put("tracking", &[("Tracking", "true")]),
+            put("tracking", &[("Tracking", "false")])
+        ]);
+    }
+
+    #[test]
+    fn status() {
+        let (address, requests) = serve(&STATUS);
+        let mut alpaca = Alpaca::new(&address, 0);
+
+        assert_eq!(alpaca.status().unwrap(), MountStatus {
+            right_asc: Angle(Sign::Positive, 1, 30, 0.0),
+            decl: Angle(Sign::Negative, 45, 15, 0.0),
+            altitude: Angle(Sign::Positive, 30, 30, 0.0),
+            azimuth: Angle(Sign::Positive, 180, 0, 0.0),
+            tracking: true,
+            tracking_mode: Some(TrackingMode::Solar),
+            slewing: false,
+            parked: false
+        });
+        assert!(take(&requests).iter().all(|request| request.method == "GET"));
+    }
+
+    #[test]
+    fn tracking_rate() {
+        // without the offset of the rate
+        let (address, _) = serve(&[("trackingrate", "0")]);
+        assert_eq!(Alpaca::new(&address, 0).tracking_mode().unwrap(), TrackingMode::Sidereal);
+
+        let (address, _) = serve(&[("trackingrate", "0"), ("rightascensionrate", "!1279")]);
+        assert!(Alpaca::new(&address, 0).tracking_mode().is_err());
+        let (address, _) = serve(&[("trackingrate", "!1279")]);
+        assert!(Alpaca::new(&address, 0).tracking_mode().is_err());
+    }
+
+    #[test]
+    fn park() {
+        let (address, requests) = serve(&STATUS);
+        let mut alpaca = Alpaca::new(&address, 0);
+
+        alpaca.handle(&UserEvent::Park(ParkPosition {
+            altitude: Angle(Sign::Positive, 10, 0, 0.0),
+            azimuth: Angle(Sign::Positive, 90, 15, 0.0)
+        })).unwrap();
+        assert_eq!(take(&requests), [
+            put("tracking", &[("Tracking", "false")]),
+            put("slewtoaltazasync", &[("Azimuth", "90.25"), ("Altitude", "10")])
+        ]);
+
+        // the slew is over: the position becomes the park position of the mount
+        alpaca.status().unwrap();
+        let puts: Vec<Request> = take(&requests).into_iter().filter(|request| request.method == "PUT").collect();
+        assert_eq!(puts, [put("setpark", &[]), put("park", &[])]);
+
+        alpaca.status().unwrap();
+        assert!(take(&requests).iter().all(|request| request.method == "GET"));
+    }
+}
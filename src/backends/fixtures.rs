@@ -0,0 +1,23 @@
+//! Fixtures shared by the tests of the backends.
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use crate::data::Angle;
+
+
+/// Starts a server on a free local port, passing each client to `handle`. Returns its address (e.g. "127.0.0.1:4242").
+pub fn serve<H: FnMut(TcpStream) + Send + 'static>(mut handle: H) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    thread::spawn(move || for stream in listener.incoming() {
+        handle(stream.unwrap());
+    });
+
+    address
+}
+
+pub fn assert_close(angle: Angle, decimal: f64, tolerance: f64) {
+    assert!((angle.to_decimal() - decimal).abs() < tolerance, "{} is not {}", angle, decimal);
+}
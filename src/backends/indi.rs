@@ -0,0 +1,425 @@
+//! INDI client: drives a telescope device of an `indiserver`, through its standard properties.
+//!
+//! The protocol is asynchronous: the server pushes the updates of the properties,
+//! which are gathered whenever the status is read.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use quick_xml::{
+    escape::escape,
+    events::{BytesStart, Event},
+    Reader
+};
+
+use crate::{
+    backends::Backend,
+    data::{Angle, AngleKind, Direction, SlewRate, TrackingMode, ParkPosition, MountStatus, horizontal_to_equatorial},
+    events::UserEvent
+};
+
+
+/// Port of `indiserver` by default
+pub const INDI_PORT: u16 = 7624;
+
+/// Time waited for the updates of the server when reading the status
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+
+pub struct Indi {
+    stream: TcpStream,
+    device: String,
+
+    /// data received, not parsed yet
+    received: Vec<u8>,
+    /// state of the device, updated with the properties sent by the server
+    status: MountStatus,
+    elevation: f64,
+    /// latitude of the site, in degrees
+    latitude: f64,
+    /// true if the park position is given in equatorial coordinates, false if in horizontal ones
+    park_equatorial: bool,
+    /// rate of the right ascension axis in the custom tracking mode, in seconds of arc per second
+    track_rate: f64
+}
+
+impl Indi {
+    /// Connects to the server at `address` (e.g. ("localhost", INDI_PORT)) and to its telescope `device`
+    /// (e.g. "Telescope Simulator")
+    pub fn connect<A: ToSocketAddrs>(address: A, device: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let mut indi = Indi {
+            stream,
+            device: device.to_string(),
+            received: vec![],
+            status: MountStatus::default(),
+            elevation: 0.0,
+            latitude: 0.0,
+            park_equatorial: true,
+            track_rate: TrackingMode::Sidereal.rate()
+        };
+
+        let get_properties = format!("<getProperties version=\"1.7\" device=\"{}\"/>", escape(device));
+        indi.stream.write_all(get_properties.as_bytes())?;
+        indi.new_switches("CONNECTION", &[("CONNECT", true)])?;
+
+        Ok(indi)
+    }
+
+    /// Stops any motion of the telescope
+    pub fn abort(&mut self) -> io::Result<()> {
+        self.new_switches("TELESCOPE_ABORT_MOTION", &[("ABORT", true)])
+    }
+
+    fn new_vector(&mut self, kind: &str, property: &str, members: &[(&str, String)]) -> io::Result<()> {
+        let mut xml = format!("<new{}Vector device=\"{}\" name=\"{}\">", kind, escape(&self.device), property);
+        for (name, value) in members {
+            xml += &format!("<one{} name=\"{}\">{}</one{}>", kind, name, value, kind);
+        }
+        xml += &format!("</new{}Vector>", kind);
+
+        self.stream.write_all(xml.as_bytes())
+    }
+
+    fn new_numbers(&mut self, property: &str, members: &[(&str, f64)]) -> io::Result<()> {
+        let members: Vec<(&str, String)> = members.iter().map(|(name, value)| (*name, value.to_string())).collect();
+        self.new_vector("Number", property, &members)
+    }
+
+    fn new_switches(&mut self, property: &str, members: &[(&str, bool)]) -> io::Result<()> {
+        let members: Vec<(&str, String)> = members.iter()
+            .map(|(name, on)| (*name, if *on { "On" } else { "Off" }.to_string()))
+            .collect();
+        self.new_vector("Switch", property, &members)
+    }
+
+    /// Selects the rate of the manual moves, among the four standard ones
+    fn set_slew_rate(&mut self, rate: SlewRate) -> io::Result<()> {
+        let member = match rate.preset() {
+            SlewRate::Guide => "SLEW_GUIDE",
+            SlewRate::Centering => "SLEW_CENTERING",
+            SlewRate::Find => "SLEW_FIND",
+            _ => "SLEW_MAX"
+        };
+        self.new_switches("TELESCOPE_SLEW_RATE", &[(member, true)])
+    }
+
+    /// Selects the tracking mode, then enables the tracking (or disables it if `mode` is None)
+    fn track(&mut self, mode: Option<TrackingMode>) -> io::Result<()> {
+        let mode = match mode {
+            Some(mode) => mode,
+            None => return self.new_switches("TELESCOPE_TRACK_STATE", &[("TRACK_OFF", true)])
+        };
+
+        match mode {
+            TrackingMode::Sidereal => self.new_switches("TELESCOPE_TRACK_MODE", &[("TRACK_SIDEREAL", true)])?,
+            TrackingMode::Lunar => self.new_switches("TELESCOPE_TRACK_MODE", &[("TRACK_LUNAR", true)])?,
+            TrackingMode::Solar => self.new_switches("TELESCOPE_TRACK_MODE", &[("TRACK_SOLAR", true)])?,
+            // the standard modes stop at the solar rate
+            TrackingMode::King | TrackingMode::Custom(_) => {
+                self.new_switches("TELESCOPE_TRACK_MODE", &[("TRACK_CUSTOM", true)])?;
+                self.new_numbers("TELESCOPE_TRACK_RATE", &[("TRACK_RATE_RA", mode.rate()), ("TRACK_RATE_DE", 0.0)])?;
+            }
+        }
+        self.new_switches("TELESCOPE_TRACK_STATE", &[("TRACK_ON", true)])
+    }
+
+    /// Sets the park position of the device, then parks it
+    fn park(&mut self, position: ParkPosition) -> io::Result<()> {
+        if self.park_equatorial {
+            // PARK_RA holds the hour angle, in hours
+            let (hour_angle, decl) = horizontal_to_equatorial(position.altitude, position.azimuth, Angle::from_decimal(self.latitude));
+            self.new_numbers("TELESCOPE_PARK_POSITION", &[("PARK_RA", hour_angle.to_decimal()), ("PARK_DEC", decl.to_decimal())])?;
+        } else {
+            self.new_numbers("TELESCOPE_PARK_POSITION", &[
+                ("PARK_AZ", position.azimuth.to_decimal()),
+                ("PARK_ALT", position.altitude.to_decimal())
+            ])?;
+        }
+        self.new_switches("TELESCOPE_PARK", &[("PARK", true)])
+    }
+
+    fn move_axis(&mut self, dir: Direction, moving: bool) -> io::Result<()> {
+        match dir {
+            Direction::Up => self.new_switches("TELESCOPE_MOTION_NS", &[("MOTION_NORTH", moving)]),
+            Direction::Down => self.new_switches("TELESCOPE_MOTION_NS", &[("MOTION_SOUTH", moving)]),
+            Direction::Left => self.new_switches("TELESCOPE_MOTION_WE", &[("MOTION_EAST", moving)]),
+            Direction::Right => self.new_switches("TELESCOPE_MOTION_WE", &[("MOTION_WEST", moving)])
+        }
+    }
+
+    /// Reads the updates sent by the server, without blocking more than `READ_TIMEOUT`
+    fn receive(&mut self) -> io::Result<()> {
+        let mut buffer = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the INDI server closed the connection")),
+                Ok(n) => self.received.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) => return Err(e)
+            }
+        }
+
+        while let Some(element) = next_element(&mut self.received) {
+            self.apply(&element);
+        }
+
+        Ok(())
+    }
+
+    /// Updates the status with a `defXXXVector` or `setXXXVector` element
+    fn apply(&mut self, element: &str) {
+        let mut reader = Reader::from_str(element);
+
+        let mut property = String::new();
+        let mut member = String::new();
+
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                    let tag = e.name();
+                    let tag = String::from_utf8_lossy(tag.as_ref());
+                    if tag.ends_with("Vector") {
+                        if attribute(&e, "device").as_deref() != Some(self.device.as_str()) {
+                            return;
+                        }
+                        property = attribute(&e, "name").unwrap_or_default();
+                        if property == "EQUATORIAL_EOD_COORD" {
+                            self.status.slewing = attribute(&e, "state").as_deref() == Some("Busy");
+                        }
+                    } else {
+                        member = attribute(&e, "name").unwrap_or_default();
+                    }
+                },
+                Ok(Event::Text(e)) => {
+                    let value = match e.unescape() {
+                        Ok(value) => value.trim().to_string(),
+                        Err(_) => continue
+                    };
+                    self.apply_member(&property, &member, &value);
+                },
+                Ok(Event::Eof) | Err(_) => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn apply_member(&mut self, property: &str, member: &str, value: &str) {
+        match (property, member, parse_number(value)) {
+            ("EQUATORIAL_EOD_COORD", "RA", Some(v)) => self.status.right_asc = AngleKind::RightAscension.normalize(Angle::from_decimal(v)),
+            ("EQUATORIAL_EOD_COORD", "DEC", Some(v)) => self.status.decl = Angle::from_decimal(v),
+            ("HORIZONTAL_COORD", "ALT", Some(v)) => self.status.altitude = Angle::from_decimal(v),
+            ("HORIZONTAL_COORD", "AZ", Some(v)) => self.status.azimuth = Angle::from_decimal(v),
+            ("GEOGRAPHIC_COORD", "ELEV", Some(v)) => self.elevation = v,
+            ("GEOGRAPHIC_COORD", "LAT", Some(v)) => self.latitude = v,
+            ("TELESCOPE_PARK_POSITION", "PARK_RA", _) => self.park_equatorial = true,
+            ("TELESCOPE_PARK_POSITION", "PARK_AZ", _) => self.park_equatorial = false,
+            ("TELESCOPE_PARK", "PARK", _) => self.status.parked = value == "On",
+            ("TELESCOPE_TRACK_STATE", "TRACK_ON", _) => self.status.tracking = value == "On",
+            ("TELESCOPE_TRACK_MODE", _, _) if value == "On" => {
+                self.status.tracking_mode = match member {
+                    "TRACK_SIDEREAL" => Some(TrackingMode::Sidereal),
+                    "TRACK_LUNAR" => Some(TrackingMode::Lunar),
+                    "TRACK_SOLAR" => Some(TrackingMode::Solar),
+                    "TRACK_CUSTOM" => Some(custom_mode(self.track_rate)),
+                    _ => None
+                };
+            },
+            ("TELESCOPE_TRACK_RATE", "TRACK_RATE_RA", Some(v)) => {
+                self.track_rate = v;
+                if let Some(TrackingMode::King) | Some(TrackingMode::Custom(_)) = self.status.tracking_mode {
+                    self.status.tracking_mode = Some(custom_mode(v));
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Backend for Indi {
+    fn handle(&mut self, event: &UserEvent) -> io::Result<()> {
+        match *event {
+            UserEvent::Rotate(right_asc, decl) => {
+                // slew, then track the target
+                self.new_switches("ON_COORD_SET", &[("TRACK", true)])?;
+                self.new_numbers("EQUATORIAL_EOD_COORD", &[("RA", right_asc.to_decimal()), ("DEC", decl.to_decimal())])
+            },
+            UserEvent::Sync(right_asc, decl) => {
+                self.new_switches("ON_COORD_SET", &[("SYNC", true)])?;
+                self.new_numbers("EQUATORIAL_EOD_COORD", &[("RA", right_asc.to_decimal()), ("DEC", decl.to_decimal())])
+            },
+            UserEvent::BeginMove(dir, rate) => {
+                self.set_slew_rate(rate)?;
+                self.move_axis(dir, true)
+            },
+            UserEvent::EndMove(dir) => self.move_axis(dir, false),
+            UserEvent::Position(longitude, latitude) => {
+                // INDI longitudes are measured eastward, from 0 to 360°
+                let elevation = self.elevation;
+                self.new_numbers("GEOGRAPHIC_COORD", &[
+                    ("LAT", latitude.to_decimal()),
+                    ("LONG", longitude.to_decimal().rem_euclid(360.0)),
+                    ("ELEV", elevation)
+                ])
+            },
+            UserEvent::Zero | UserEvent::KeepMoving => Ok(()),
+            UserEvent::Abort => self.abort(),
+            UserEvent::Track(mode) => self.track(mode),
+            UserEvent::Park(position) => self.park(position),
+            UserEvent::Unpark => self.new_switches("TELESCOPE_PARK", &[("UNPARK", true)]),
+            UserEvent::FindHome => self.new_switches("TELESCOPE_HOME", &[("FIND", true)])
+        }
+    }
+
+    fn status(&mut self) -> io::Result<MountStatus> {
+        self.receive()?;
+        Ok(self.status)
+    }
+}
+
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element.try_get_attribute(name).ok().flatten()
+        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// INDI numbers are either decimal or sexagesimal (e.g. "-12:30:15.5")
+fn parse_number(value: &str) -> Option<f64> {
+    let negative = value.starts_with('-');
+
+    let mut number = 0.0;
+    for (i, field) in value.trim_start_matches(&['-', '+'][..]).split(':').enumerate() {
+        number += field.parse::<f64>().ok()? / 60f64.powi(i as i32);
+    }
+
+    Some(if negative { -number } else { number })
+}
+
+/// Mode tracking at the custom `rate`, King's being sent as a custom rate
+fn custom_mode(rate: f64) -> TrackingMode {
+    // the drivers may round the rate
+    if (rate - TrackingMode::King.rate()).abs() < 1e-3 {
+        TrackingMode::King
+    } else {
+        TrackingMode::Custom(rate)
+    }
+}
+
+/// Removes the first complete top level element from `received`.
+/// The elements are decoded once complete, so that a character split between two reads is kept whole.
+fn next_element(received: &mut Vec<u8>) -> Option<String> {
+    loop {
+        // the markup is ASCII, which never appears within a multi-byte character
+        let start = received.iter().position(|&b| b == b'<')?;
+        let open_end = received[start..].iter().position(|&b| b == b'>')? + start;
+        let name_end = received[start + 1..].iter()
+            .position(|&b| b.is_ascii_whitespace() || b == b'/' || b == b'>')
+            .map(|i| i + start + 1)?;
+        let name = received[start + 1..name_end].to_vec();
+
+        // declarations, comments and stray closing tags carry nothing
+        if name.starts_with(b"?") || name.starts_with(b"!") || name.starts_with(b"/") {
+            received.drain(..=open_end);
+            continue;
+        }
+
+        let end = if received[..open_end].ends_with(b"/") {
+            open_end + 1
+        } else {
+            let closing = [b"</", &name[..], b">"].concat();
+            received[open_end..].windows(closing.len()).position(|w| w == &closing[..])? + open_end + closing.len()
+        };
+
+        let element = String::from_utf8_lossy(&received[start..end]).into_owned();
+        received.drain(..end);
+        return Some(element);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::backends::fixtures;
+
+    /// Starts a server sending `updates` to its clients, then returning what they sent once they disconnect
+    fn serve(updates: &'static [u8]) -> (String, mpsc::Receiver<String>) {
+        let (sender, server) = mpsc::channel();
+        let address = fixtures::serve(move |mut stream| {
+            stream.write_all(updates).unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).unwrap();
+            let _ = sender.send(received);
+        });
+
+        (address, server)
+    }
+
+    /// Reads the status once the server updates reached the client
+    fn status_when<F: Fn(&MountStatus) -> bool>(indi: &mut Indi, ready: F) -> MountStatus {
+        for _ in 0..40 {
+            let status = indi.status().unwrap();
+            if ready(&status) {
+                return status;
+            }
+        }
+        panic!("the updates of the server were not received");
+    }
+
+    #[test]
+    fn split_character() {
+        let update = "<message device=\"Sim\" message=\"Télescope prêt\"/><message device=\"Sim\"";
+        let split = update.find('é').unwrap() + 1;
+
+        let mut received = update.as_bytes()[..split].to_vec();
+        assert_eq!(next_element(&mut received), None);
+        received.extend_from_slice(&update.as_bytes()[split..]);
+        assert_eq!(next_element(&mut received).as_deref(), Some("<message device=\"Sim\" message=\"Télescope prêt\"/>"));
+        assert_eq!(next_element(&mut received), None);
+        assert_eq!(received, b"<message device=\"Sim\"");
+    }
+
+    #[test]
+    fn king_rate() {
+        let (address, server) = serve(concat!(
+            "<setSwitchVector device=\"Sim\" name=\"TELESCOPE_TRACK_MODE\">",
+            "<oneSwitch name=\"TRACK_SIDEREAL\">Off</oneSwitch><oneSwitch name=\"TRACK_CUSTOM\">On</oneSwitch>",
+            "</setSwitchVector>",
+            "<setNumberVector device=\"Sim\" name=\"TELESCOPE_TRACK_RATE\">",
+            "<oneNumber name=\"TRACK_RATE_RA\">15.0369</oneNumber><oneNumber name=\"TRACK_RATE_DE\">0</oneNumber>",
+            "</setNumberVector>"
+        ).as_bytes());
+
+        let mut indi = Indi::connect(address, "Sim").unwrap();
+        indi.handle(&UserEvent::Track(Some(TrackingMode::King))).unwrap();
+        let status = status_when(&mut indi, |status| status.tracking_mode.is_some());
+        assert_eq!(status.tracking_mode, Some(TrackingMode::King));
+        drop(indi);
+
+        let sent = server.recv().unwrap();
+        assert!(sent.contains("<oneSwitch name=\"TRACK_CUSTOM\">On</oneSwitch>"), "{}", sent);
+        assert!(sent.contains("<oneNumber name=\"TRACK_RATE_RA\">15.0369</oneNumber>"), "{}", sent);
+    }
+
+    #[test]
+    fn custom_rate() {
+        let (address, server) = serve(concat!(
+            "<defSwitchVector device=\"Sim\" name=\"TELESCOPE_TRACK_MODE\">",
+            "<defSwitch name=\"TRACK_CUSTOM\">On</defSwitch>",
+            "</defSwitchVector>",
+            "<defNumberVector device=\"Sim\" name=\"TELESCOPE_TRACK_RATE\">",
+            "<defNumber name=\"TRACK_RATE_RA\">14.5</defNumber>",
+            "</defNumberVector>"
+        ).as_bytes());
+
+        let mut indi = Indi::connect(address, "Sim").unwrap();
+        let status = status_when(&mut indi, |status| status.tracking_mode == Some(TrackingMode::Custom(14.5)));
+        assert!(!status.tracking);
+        drop(indi);
+        server.recv().unwrap();
+    }
+}
@@ -0,0 +1,477 @@
+//! Meade LX200 serial protocol, spoken by most commercial mounts and many DIY controllers.
+//!
+//! `Lx200` works on anything readable and writable, so its tests drive it through an emulated
+//! mount instead of a serial port.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::{
+    backends::{Backend, invalid_data},
+    data::{Angle, Sign, Direction, SlewRate, TrackingMode, ParkPosition, MountStatus},
+    events::UserEvent
+};
+
+
+/// Baud rate of the LX200 serial protocol
+pub const LX200_BAUD_RATE: u32 = 9600;
+
+/// Time after which a mount not answering a command is considered disconnected
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+// set by the distance bars (`:D#`) while the mount is slewing
+const SLEWING_BAR: u8 = 0x7f;
+
+
+pub struct Lx200<P> {
+    port: P,
+    /// set by `Park`, see `Backend`
+    parked: bool,
+    /// the protocol does not report the tracking: last state set, which the mount keeps
+    tracking: bool,
+    tracking_mode: Option<TrackingMode>
+}
+
+impl Lx200<Box<dyn SerialPort>> {
+    /// Opens the serial port at `path` (e.g. "/dev/ttyUSB0")
+    pub fn open(path: &str) -> io::Result<Self> {
+        let port = serialport::new(path, LX200_BAUD_RATE)
+            .timeout(TIMEOUT)
+            .open()?;
+
+        Ok(Lx200::new(port))
+    }
+}
+
+impl<P: Read + Write> Lx200<P> {
+    pub fn new(port: P) -> Self {
+        Lx200 { port, parked: false, tracking: false, tracking_mode: None }
+    }
+
+    /// Sends a command without reply
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        self.port.write_all(command.as_bytes())?;
+        self.port.flush()
+    }
+
+    /// Sends a command whose reply is a single byte
+    fn query_byte(&mut self, command: &str) -> io::Result<u8> {
+        self.send(command)?;
+
+        let mut reply = [0u8];
+        self.port.read_exact(&mut reply)?;
+        Ok(reply[0])
+    }
+
+    /// Sends a command whose reply is a string terminated by '#'. The '#' is not returned.
+    fn query_string(&mut self, command: &str) -> io::Result<Vec<u8>> {
+        self.send(command)?;
+        self.read_string()
+    }
+
+    fn read_string(&mut self) -> io::Result<Vec<u8>> {
+        let mut reply = vec![];
+        let mut byte = [0u8];
+        loop {
+            self.port.read_exact(&mut byte)?;
+            if byte[0] == b'#' {
+                return Ok(reply);
+            }
+            reply.push(byte[0]);
+        }
+    }
+
+    /// Sends a "set" command, to which the mount replies '1' when valid
+    fn set(&mut self, command: &str) -> io::Result<()> {
+        match self.query_byte(command)? {
+            b'1' => Ok(()),
+            _ => Err(invalid_data(format!("the mount rejected {}", command)))
+        }
+    }
+
+    fn set_target(&mut self, right_asc: Angle, decl: Angle) -> io::Result<()> {
+        // the high precision mode goes down to the second
+        let (right_asc, decl) = (right_asc.round(0), decl.round(0));
+        self.set(&format!(":Sr{:02}:{:02}:{:02.0}#", right_asc.1, right_asc.2, right_asc.3))?;
+        self.set(&format!(":Sd{}{:02}*{:02}:{:02.0}#", sign(decl), decl.1, decl.2, decl.3))
+    }
+
+    fn slew(&mut self, right_asc: Angle, decl: Angle) -> io::Result<()> {
+        self.set_target(right_asc, decl)?;
+
+        // '0' when the slew is possible, else '1' or '2' followed by an explanation
+        match self.query_byte(":MS#")? {
+            b'0' => Ok(()),
+            _ => {
+                let reason = self.read_string()?;
+                Err(invalid_data(String::from_utf8_lossy(&reason).into_owned()))
+            }
+        }
+    }
+
+    /// Makes the target the current position
+    fn sync(&mut self, right_asc: Angle, decl: Angle) -> io::Result<()> {
+        self.set_target(right_asc, decl)?;
+        // replies the name of the matched object, if any
+        self.query_string(":CM#").map(|_| ())
+    }
+
+    /// Selects the tracking rate, then enables the tracking (or disables it if `mode` is None)
+    fn track(&mut self, mode: Option<TrackingMode>) -> io::Result<()> {
+        let mode = match mode {
+            Some(mode) => mode,
+            None => {
+                self.set(":Td#")?;
+                self.tracking = false;
+                return Ok(());
+            }
+        };
+
+        match mode {
+            TrackingMode::Sidereal => self.send(":TQ#")?,
+            TrackingMode::Lunar => self.send(":TL#")?,
+            TrackingMode::Solar => self.send(":TS#")?,
+            TrackingMode::King => self.send(":TK#")?,
+            TrackingMode::Custom(rate) => {
+                // given as the frequency of a synchronous motor, 60 Hz being the sidereal rate
+                let frequency = 60.0 * rate / TrackingMode::Sidereal.rate();
+                self.send(":TM#")?;
+                self.set(&format!(":ST{:08.5}#", frequency))?;
+            }
+        }
+        self.set(":Te#")?;
+        self.tracking = true;
+        self.tracking_mode = Some(mode);
+        Ok(())
+    }
+
+    /// Slews to the position, then stops the tracking so that the mount stays there
+    fn park(&mut self, position: ParkPosition) -> io::Result<()> {
+        let (altitude, azimuth) = (position.altitude.round(0), position.azimuth.round(0));
+        self.set(&format!(":Sa{}{:02}*{:02}'{:02.0}#", sign(altitude), altitude.1, altitude.2, altitude.3))?;
+        self.set(&format!(":Sz{:03}*{:02}'{:02.0}#", azimuth.1, azimuth.2, azimuth.3))?;
+
+        // '0' when the slew is possible
+        match self.query_byte(":MA#")? {
+            b'0' => {},
+            _ => return Err(invalid_data("the mount cannot reach the park position".to_string()))
+        }
+        self.set(":Td#")?;
+        self.tracking = false;
+        self.parked = true;
+        Ok(())
+    }
+
+    fn unpark(&mut self) -> io::Result<()> {
+        self.set(":Te#")?;
+        self.tracking = true;
+        self.parked = false;
+        Ok(())
+    }
+
+    fn set_site(&mut self, longitude: Angle, latitude: Angle) -> io::Result<()> {
+        // LX200 longitudes are measured westward, from 0 to 360°
+        let east = to_minutes(longitude).rem_euclid(360 * 60);
+        let west = (360 * 60 - east) % (360 * 60);
+        self.set(&format!(":Sg{:03}*{:02}#", west / 60, west % 60))?;
+
+        let minutes = to_minutes(latitude).abs();
+        self.set(&format!(":St{}{:02}*{:02}#", sign(latitude), minutes / 60, minutes % 60))
+    }
+}
+
+impl<P: Read + Write> Backend for Lx200<P> {
+    fn handle(&mut self, event: &UserEvent) -> io::Result<()> {
+        match *event {
+            UserEvent::Rotate(right_asc, decl) => self.slew(right_asc, decl),
+            UserEvent::Sync(right_asc, decl) => self.sync(right_asc, decl),
+            UserEvent::BeginMove(dir, rate) => {
+                self.send(rate_command(rate))?;
+                self.send(&format!(":M{}#", direction_letter(dir)))
+            },
+            UserEvent::EndMove(dir) => self.send(&format!(":Q{}#", direction_letter(dir))),
+            UserEvent::Position(longitude, latitude) => self.set_site(longitude, latitude),
+            UserEvent::Zero | UserEvent::KeepMoving => Ok(()),
+            UserEvent::Abort => self.send(":Q#"),
+            UserEvent::Track(mode) => self.track(mode),
+            UserEvent::Park(position) => self.park(position),
+            UserEvent::Unpark => self.unpark(),
+            UserEvent::FindHome => self.send(":hF#")
+        }
+    }
+
+    fn status(&mut self) -> io::Result<MountStatus> {
+        let right_asc = parse_angle(&self.query_string(":GR#")?)?;
+        let decl = parse_angle(&self.query_string(":GD#")?)?;
+        let altitude = parse_angle(&self.query_string(":GA#")?)?;
+        let azimuth = parse_angle(&self.query_string(":GZ#")?)?;
+        let slewing = self.query_string(":D#")?.contains(&SLEWING_BAR);
+
+        Ok(MountStatus {
+            right_asc,
+            decl,
+            altitude,
+            azimuth,
+            tracking: self.tracking,
+            tracking_mode: self.tracking_mode,
+            slewing,
+            parked: self.parked
+        })
+    }
+}
+
+
+fn sign(angle: Angle) -> char {
+    if angle.is_negative() { '-' } else { '+' }
+}
+
+/// Rounds an angle to the nearest minute
+fn to_minutes(angle: Angle) -> i64 {
+    (angle.to_seconds() / 60.0).round() as i64
+}
+
+fn direction_letter(dir: Direction) -> char {
+    match dir {
+        Direction::Up => 'n',
+        Direction::Down => 's',
+        Direction::Left => 'e',
+        Direction::Right => 'w'
+    }
+}
+
+/// Selects the rate of the manual moves, the protocol only knowing four of them
+fn rate_command(rate: SlewRate) -> &'static str {
+    match rate.preset() {
+        SlewRate::Guide => ":RG#",
+        SlewRate::Centering => ":RC#",
+        SlewRate::Find => ":RM#",
+        _ => ":RS#"
+    }
+}
+
+/// Parses replies such as "12:34:56", "+45*12'34", "12:34.5" (low precision) or "+45*12"
+fn parse_angle(reply: &[u8]) -> io::Result<Angle> {
+    let text = String::from_utf8_lossy(reply);
+    let invalid = || invalid_data(format!("invalid angle {}", text));
+
+    let negative = text.trim_start().starts_with('-');
+    // low precision: the minutes are followed by tenths of minute
+    let tenths = text.contains('.');
+
+    let fields = text.split(|c: char| !c.is_ascii_digit())
+        .filter(|field| !field.is_empty())
+        .map(|field| field.parse::<u16>().map_err(|_| invalid()))
+        .collect::<io::Result<Vec<u16>>>()?;
+
+    let (high, mid, low) = match fields.as_slice() {
+        [high, mid] => (*high, *mid, 0),
+        [high, mid, low] if tenths => (*high, *mid, *low * 6),
+        [high, mid, low] => (*high, *mid, *low),
+        _ => return Err(invalid())
+    };
+
+    if mid >= 60 || low >= 60 {
+        return Err(invalid());
+    }
+
+    Ok(Angle(if negative { Sign::Negative } else { Sign::Positive }, high, mid as u8, low as f64))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stand-in for a LX200 mount, used as the port of `Lx200`: replies to the commands
+    /// written like a mount would. Slews are instantaneous.
+    #[derive(Default)]
+    struct Lx200Emulator {
+        right_asc: Angle,
+        decl: Angle,
+        altitude: Angle,
+        azimuth: Angle,
+        tracking: bool,
+        target_right_asc: Angle,
+        target_decl: Angle,
+        target_altitude: Angle,
+        target_azimuth: Angle,
+
+        /// every command received, without the leading ':' and trailing '#'
+        received: Vec<String>,
+        /// bytes written which do not form a complete command yet
+        command: String,
+        /// bytes of the replies not read yet
+        replies: Vec<u8>
+    }
+
+    impl Lx200Emulator {
+        /// Bytes sent back by the mount for a command given without its leading ':' and trailing '#'
+        fn reply(&mut self, command: &str) -> Option<String> {
+            self.received.push(command.to_string());
+
+            let (name, args) = command.split_at(command.len().min(2));
+            let angle = || parse_angle(args.as_bytes());
+            let valid = |angle: io::Result<Angle>| angle.ok().map(|angle| (angle, "1".to_string()));
+            match name {
+                "Sr" => valid(angle()).map(|(angle, reply)| { self.target_right_asc = angle; reply }),
+                "Sd" => valid(angle()).map(|(angle, reply)| { self.target_decl = angle; reply }),
+                "Sa" => valid(angle()).map(|(angle, reply)| { self.target_altitude = angle; reply }),
+                "Sz" => valid(angle()).map(|(angle, reply)| { self.target_azimuth = angle; reply }),
+                "MS" => {
+                    self.right_asc = self.target_right_asc;
+                    self.decl = self.target_decl;
+                    Some("0".into())
+                },
+                "MA" => {
+                    self.altitude = self.target_altitude;
+                    self.azimuth = self.target_azimuth;
+                    Some("0".into())
+                },
+                "CM" => {
+                    self.right_asc = self.target_right_asc;
+                    self.decl = self.target_decl;
+                    Some(" M31 EX GAL MAG 3.5 SZ178.0'#".into())
+                },
+                "Te" | "Td" => {
+                    self.tracking = name == "Te";
+                    Some("1".into())
+                },
+                "Sg" | "St" | "ST" => Some("1".into()),
+                "GR" => Some(format!("{:02}:{:02}:{:02.0}#", self.right_asc.1, self.right_asc.2, self.right_asc.3)),
+                "GD" => Some(format!("{}{:02}*{:02}'{:02.0}#", sign(self.decl), self.decl.1, self.decl.2, self.decl.3)),
+                "GA" => Some(format!("{}{:02}*{:02}'{:02.0}#", sign(self.altitude), self.altitude.1, self.altitude.2, self.altitude.3)),
+                "GZ" => Some(format!("{:03}*{:02}'{:02.0}#", self.azimuth.1, self.azimuth.2, self.azimuth.3)),
+                "D" => Some("#".into()),
+                _ => None
+            }
+        }
+    }
+
+    impl Write for Lx200Emulator {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for &byte in buf {
+                if byte != b'#' {
+                    self.command.push(byte as char);
+                    continue;
+                }
+
+                // ':' may also appear inside the command (e.g. ":Sr12:30:00#")
+                let command = std::mem::take(&mut self.command);
+                let reply = match command.find(':') {
+                    Some(start) => self.reply(&command[start + 1..]),
+                    None => None
+                };
+                if let Some(reply) = reply {
+                    self.replies.extend(reply.bytes());
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Lx200Emulator {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.replies.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no reply"));
+            }
+
+            let n = buf.len().min(self.replies.len());
+            buf[..n].copy_from_slice(&self.replies[..n]);
+            self.replies.drain(..n);
+            Ok(n)
+        }
+    }
+
+    /// Commands received by the emulator since the last call
+    fn received(mount: &mut Lx200<Lx200Emulator>) -> Vec<String> {
+        std::mem::take(&mut mount.port.received)
+    }
+
+    #[test]
+    fn rotate_and_sync() {
+        let mut mount = Lx200::new(Lx200Emulator::default());
+
+        mount.handle(&UserEvent::Rotate(Angle(Sign::Positive, 12, 30, 15.4), Angle(Sign::Negative, 5, 1, 2.0))).unwrap();
+        assert_eq!(received(&mut mount), ["Sr12:30:15", "Sd-05*01:02", "MS"]);
+
+        let status = mount.status().unwrap();
+        assert_eq!((status.right_asc, status.decl), (Angle(Sign::Positive, 12, 30, 15.0), Angle(Sign::Negative, 5, 1, 2.0)));
+        assert!(!status.slewing && !status.parked);
+
+        mount.handle(&UserEvent::Sync(Angle(Sign::Positive, 0, 42, 44.0), Angle(Sign::Positive, 41, 16, 9.0))).unwrap();
+        received(&mut mount);
+        let status = mount.status().unwrap();
+        assert_eq!((status.right_asc, status.decl), (Angle(Sign::Positive, 0, 42, 44.0), Angle(Sign::Positive, 41, 16, 9.0)));
+        assert_eq!(received(&mut mount), ["GR", "GD", "GA", "GZ", "D"]);
+    }
+
+    #[test]
+    fn moves() {
+        let mut mount = Lx200::new(Lx200Emulator::default());
+
+        mount.handle(&UserEvent::BeginMove(Direction::Up, SlewRate::Max)).unwrap();
+        mount.handle(&UserEvent::BeginMove(Direction::Left, SlewRate::Guide)).unwrap();
+        mount.handle(&UserEvent::EndMove(Direction::Up)).unwrap();
+        mount.handle(&UserEvent::BeginMove(Direction::Right, SlewRate::Sidereal(8.0))).unwrap();
+        mount.handle(&UserEvent::Abort).unwrap();
+        assert_eq!(received(&mut mount), ["RS", "Mn", "RG", "Me", "Qn", "RC", "Mw", "Q"]);
+    }
+
+    #[test]
+    fn position() {
+        let mut mount = Lx200::new(Lx200Emulator::default());
+
+        // 2°20' east, 48°50'40" north
+        mount.handle(&UserEvent::Position(Angle(Sign::Positive, 2, 20, 0.0), Angle(Sign::Positive, 48, 50, 40.0))).unwrap();
+        // 70°30' west, 33°27' south
+        mount.handle(&UserEvent::Position(Angle(Sign::Negative, 70, 30, 0.0), Angle(Sign::Negative, 33, 27, 0.0))).unwrap();
+        assert_eq!(received(&mut mount), ["Sg357*40", "St+48*51", "Sg070*30", "St-33*27"]);
+    }
+
+    #[test]
+    fn track() {
+        let mut mount = Lx200::new(Lx200Emulator::default());
+
+        mount.handle(&UserEvent::Track(Some(TrackingMode::Lunar))).unwrap();
+        assert!(mount.port.tracking);
+        assert_eq!(received(&mut mount), ["TL", "Te"]);
+        let status = mount.status().unwrap();
+        assert_eq!((status.tracking, status.tracking_mode), (true, Some(TrackingMode::Lunar)));
+        received(&mut mount);
+
+        let custom = TrackingMode::Custom(TrackingMode::Sidereal.rate() / 2.0);
+        mount.handle(&UserEvent::Track(Some(custom))).unwrap();
+        mount.handle(&UserEvent::Track(None)).unwrap();
+        assert!(!mount.port.tracking);
+        assert_eq!(received(&mut mount), ["TM", "ST30.00000", "Te", "Td"]);
+        let status = mount.status().unwrap();
+        assert_eq!((status.tracking, status.tracking_mode), (false, Some(custom)));
+    }
+
+    #[test]
+    fn park() {
+        let mut mount = Lx200::new(Lx200Emulator::default());
+        mount.handle(&UserEvent::Track(Some(TrackingMode::Sidereal))).unwrap();
+        received(&mut mount);
+
+        let position = ParkPosition { altitude: Angle(Sign::Negative, 10, 0, 0.0), azimuth: Angle(Sign::Positive, 185, 30, 0.0) };
+        mount.handle(&UserEvent::Park(position)).unwrap();
+        assert_eq!(received(&mut mount), ["Sa-10*00'00", "Sz185*30'00", "MA", "Td"]);
+        assert!(!mount.port.tracking);
+
+        let status = mount.status().unwrap();
+        assert!(status.parked && !status.tracking);
+        assert_eq!((status.altitude, status.azimuth), (position.altitude, position.azimuth));
+
+        mount.handle(&UserEvent::Unpark).unwrap();
+        let status = mount.status().unwrap();
+        assert!(mount.port.tracking && status.tracking && !status.parked);
+    }
+}
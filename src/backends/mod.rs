@@ -0,0 +1,241 @@
+//! Backends driving a mount with the commands emitted by the window.
+
+use std::io;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::{
+    data::{Direction, MountStatus},
+    events::{UserEvent, Command, BackendEvent}
+};
+
+mod lx200;
+mod alpaca;
+mod indi;
+mod nexstar;
+#[cfg(test)]
+mod scripted;
+#[cfg(test)]
+mod fixtures;
+mod simulator;
+
+pub use self::lx200::*;
+pub use self::alpaca::*;
+pub use self::indi::*;
+pub use self::nexstar::*;
+pub use self::simulator::*;
+
+
+/// Time after which the manual moves are stopped if the window sends no `KeepMoving`,
+/// e.g. because it is stuck
+pub const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(1);
+
+
+/// A mount, driven by the `UserEvent`s of the window.
+///
+/// The protocols of the mounts do not cover every event:
+/// - without a user defined origin, `Zero` does nothing.
+/// - without a park position of their own, `Park` is a slew followed by stopping the tracking,
+///   and the backend remembers that the mount is parked until `Unpark`.
+pub trait Backend {
+    /// Executes the event. An error means that the mount rejected it.
+    /// `run` keeps the `KeepMoving` heartbeats for its watchdog: they are never passed to the backend.
+    fn handle(&mut self, event: &UserEvent) -> io::Result<()>;
+
+    /// Reads the current state of the mount
+    fn status(&mut self) -> io::Result<MountStatus>;
+}
+
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+
+/// Manual moves in progress, which are stopped when the window stops sending heartbeats
+#[derive(Default)]
+struct Watchdog {
+    moving: Vec<Direction>,
+    last_heartbeat: Option<Instant>
+}
+
+impl Watchdog {
+    fn follow(&mut self, event: &UserEvent) {
+        match *event {
+            UserEvent::BeginMove(dir, _) => {
+                if !self.moving.contains(&dir) {
+                    self.moving.push(dir);
+                }
+                self.last_heartbeat = Some(Instant::now());
+            },
+            UserEvent::EndMove(dir) => self.moving.retain(|&d| d != dir),
+            UserEvent::KeepMoving => self.last_heartbeat = Some(Instant::now()),
+            UserEvent::Abort => self.moving.clear(),
+            _ => {}
+        }
+    }
+
+    /// Time left before the moves must be stopped, None if there is no move
+    fn remaining(&self) -> Option<Duration> {
+        match self.last_heartbeat {
+            Some(instant) if !self.moving.is_empty() => Some(WATCHDOG_TIMEOUT.checked_sub(instant.elapsed()).unwrap_or_default()),
+            _ => None
+        }
+    }
+
+    /// Stops every move in progress
+    fn stop<B: Backend>(&mut self, backend: &mut B) {
+        for dir in self.moving.drain(..) {
+            // nothing more can be done if the mount does not answer
+            let _ = backend.handle(&UserEvent::EndMove(dir));
+        }
+    }
+}
+
+
+/// Executes the commands of the window until it is closed.
+/// Every command is acknowledged, and the state of the mount is reported at least every `period`.
+/// The manual moves are stopped if the window closes, or if it sends no heartbeat for `WATCHDOG_TIMEOUT`.
+pub fn run<B: Backend>(
+    backend: &mut B,
+    commands: mpsc::Receiver<Command>,
+    events: mpsc::Sender<BackendEvent>,
+    period: Duration) {
+    run_with_observers(backend, commands, events, vec![], period);
+}
+
+/// Same as `run`, also sending every status to the `observers`, e.g. the Stellarium server.
+/// An observer which is gone is simply forgotten.
+pub fn run_with_observers<B: Backend>(
+    backend: &mut B,
+    commands: mpsc::Receiver<Command>,
+    events: mpsc::Sender<BackendEvent>,
+    mut observers: Vec<mpsc::Sender<MountStatus>>,
+    period: Duration) {
+    let mut last_status: Option<Instant> = None;
+    let mut watchdog = Watchdog::default();
+
+    loop {
+        let mut timeout = match last_status {
+            Some(instant) => period.checked_sub(instant.elapsed()).unwrap_or_default(),
+            None => Duration::from_secs(0)
+        };
+        if let Some(remaining) = watchdog.remaining() {
+            timeout = timeout.min(remaining);
+        }
+
+        match commands.recv_timeout(timeout) {
+            Ok(command) => {
+                watchdog.follow(&command.event);
+                let result = match command.event {
+                    UserEvent::KeepMoving => Ok(()),
+                    ref event => backend.handle(event)
+                };
+                let ack = match result {
+                    Ok(()) => BackendEvent::Accepted(command.seq),
+                    Err(_) => BackendEvent::Rejected(command.seq)
+                };
+                if events.send(ack).is_err() {
+                    watchdog.stop(backend);
+                    return;
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {},
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                watchdog.stop(backend);
+                return;
+            }
+        }
+
+        if watchdog.remaining() == Some(Duration::from_secs(0)) {
+            watchdog.stop(backend);
+        }
+
+        let due = match last_status {
+            Some(instant) => instant.elapsed() >= period,
+            None => true
+        };
+        if due {
+            last_status = Some(Instant::now());
+            if let Ok(status) = backend.status() {
+                observers.retain(|observer| observer.send(status).is_ok());
+                if events.send(BackendEvent::Status(status)).is_err() {
+                    watchdog.stop(backend);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::data::SlewRate;
+
+    /// Records the events it handles
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<UserEvent>
+    }
+
+    impl Backend for Recorder {
+        fn handle(&mut self, event: &UserEvent) -> io::Result<()> {
+            self.events.push(*event);
+            Ok(())
+        }
+
+        fn status(&mut self) -> io::Result<MountStatus> {
+            Ok(MountStatus::default())
+        }
+    }
+
+    #[test]
+    fn heartbeats() {
+        let (command_sender, commands) = mpsc::channel();
+        let (events, event_receiver) = mpsc::channel();
+        let backend = thread::spawn(move || {
+            let mut recorder = Recorder::default();
+            run(&mut recorder, commands, events, Duration::from_secs(60));
+            recorder.events
+        });
+
+        let sequence = [
+            UserEvent::BeginMove(Direction::Up, SlewRate::Max),
+            UserEvent::KeepMoving,
+            UserEvent::KeepMoving,
+            UserEvent::EndMove(Direction::Up)
+        ];
+        for (seq, &event) in sequence.iter().enumerate() {
+            command_sender.send(Command { seq: seq as u64, event }).unwrap();
+        }
+        drop(command_sender);
+
+        let acks: Vec<BackendEvent> = event_receiver.iter()
+            .filter(|event| !matches!(event, BackendEvent::Status(_)))
+            .collect();
+        assert_eq!(acks, (0..4).map(BackendEvent::Accepted).collect::<Vec<_>>());
+        assert_eq!(backend.join().unwrap(), vec![sequence[0], sequence[3]]);
+    }
+
+    #[test]
+    fn observers() {
+        let (command_sender, commands) = mpsc::channel();
+        let (events, event_receiver) = mpsc::channel();
+        let (observer, statuses) = mpsc::channel();
+        let (gone, _) = mpsc::channel();
+        let backend = thread::spawn(move || {
+            run_with_observers(&mut Recorder::default(), commands, events, vec![gone, observer], Duration::from_millis(10));
+        });
+
+        for _ in 0..3 {
+            assert_eq!(statuses.recv().unwrap(), MountStatus::default());
+            assert_eq!(event_receiver.recv().unwrap(), BackendEvent::Status(MountStatus::default()));
+        }
+        drop(command_sender);
+        backend.join().unwrap();
+    }
+}
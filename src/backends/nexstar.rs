@@ -0,0 +1,325 @@
+//! Celestron NexStar serial protocol, also spoken by the SkyWatcher SynScan hand controllers.
+//!
+//! Positions are exchanged as fractions of a revolution, written as 32 bits hexadecimal numbers
+//! (e.g. "34AB0500,12CE0500"). `NexStar` works on anything readable and writable, so its tests
+//! replay scripted exchanges instead of using a serial port.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::{
+    backends::{Backend, invalid_data},
+    data::{Angle, AngleKind, Direction, TrackingMode, ParkPosition, MountStatus},
+    events::UserEvent
+};
+
+
+/// Baud rate of the NexStar serial protocol
+pub const NEXSTAR_BAUD_RATE: u32 = 9600;
+
+/// Time after which a mount not answering a command is considered disconnected
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Fastest rate of the manual moves, in degrees per second
+const MAX_MOVE_RATE: f64 = 3.0;
+
+// passthrough commands to the motor controllers
+const AZM_RA_MOTOR: u8 = 16;
+const ALT_DEC_MOTOR: u8 = 17;
+const POSITIVE_VARIABLE_RATE: u8 = 6;
+const NEGATIVE_VARIABLE_RATE: u8 = 7;
+
+// full circle in seconds of time and seconds of arc
+const DAY_SECONDS: f64 = 24.0 * 3600.0;
+const TURN_SECONDS: f64 = 360.0 * 3600.0;
+
+// positions are fractions of this
+const REVOLUTION: f64 = 4_294_967_296.0;
+
+
+pub struct NexStar<P> {
+    port: P,
+    /// true if the site is in the southern hemisphere, where the equatorial tracking is reversed
+    southern: bool,
+    /// set by `Park`, see `Backend`
+    parked: bool
+}
+
+impl NexStar<Box<dyn SerialPort>> {
+    /// Opens the serial port at `path` (e.g. "/dev/ttyUSB0")
+    pub fn open(path: &str) -> io::Result<Self> {
+        let port = serialport::new(path, NEXSTAR_BAUD_RATE)
+            .timeout(TIMEOUT)
+            .open()?;
+
+        Ok(NexStar::new(port))
+    }
+}
+
+impl<P: Read + Write> NexStar<P> {
+    pub fn new(port: P) -> Self {
+        NexStar { port, southern: false, parked: false }
+    }
+
+    /// Sends a command, and returns its reply without the terminating '#'
+    fn query(&mut self, command: &[u8]) -> io::Result<Vec<u8>> {
+        self.port.write_all(command)?;
+        self.port.flush()?;
+
+        let mut reply = vec![];
+        let mut byte = [0u8];
+        loop {
+            self.port.read_exact(&mut byte)?;
+            if byte[0] == b'#' {
+                return Ok(reply);
+            }
+            reply.push(byte[0]);
+        }
+    }
+
+    /// Reads a pair of positions, as fractions of a revolution
+    fn query_position(&mut self, command: &[u8]) -> io::Result<(u32, u32)> {
+        let reply = self.query(command)?;
+        let text = String::from_utf8_lossy(&reply);
+        let invalid = || invalid_data(format!("invalid position {}", text));
+
+        let (first, second) = text.split_once(',').ok_or_else(invalid)?;
+        Ok((
+            u32::from_str_radix(first, 16).map_err(|_| invalid())?,
+            u32::from_str_radix(second, 16).map_err(|_| invalid())?
+        ))
+    }
+
+    fn slew(&mut self, right_asc: Angle, decl: Angle) -> io::Result<()> {
+        let right_asc = to_fraction(right_asc.to_seconds() / DAY_SECONDS);
+        let decl = to_fraction(decl.to_seconds() / TURN_SECONDS);
+
+        self.query(format!("r{:08X},{:08X}", right_asc, decl).as_bytes()).map(|_| ())
+    }
+
+    fn sync(&mut self, right_asc: Angle, decl: Angle) -> io::Result<()> {
+        let right_asc = to_fraction(right_asc.to_seconds() / DAY_SECONDS);
+        let decl = to_fraction(decl.to_seconds() / TURN_SECONDS);
+
+        self.query(format!("s{:08X},{:08X}", right_asc, decl).as_bytes()).map(|_| ())
+    }
+
+    fn set_location(&mut self, longitude: Angle, latitude: Angle) -> io::Result<()> {
+        // the longitude is given between 0 and 180°, either east or west
+        let longitude = AngleKind::Longitude.normalize(longitude).round(0);
+        let latitude = latitude.round(0);
+        self.southern = latitude.is_negative();
+
+        self.query(&[
+            b'W',
+            latitude.1 as u8, latitude.2, latitude.3 as u8, latitude.is_negative() as u8,
+            longitude.1 as u8, longitude.2, longitude.3 as u8, longitude.is_negative() as u8
+        ]).map(|_| ())
+    }
+
+    /// Enables the equatorial tracking at the sidereal rate, the only one of the protocol,
+    /// or disables it if `mode` is None
+    fn track(&mut self, mode: Option<TrackingMode>) -> io::Result<()> {
+        let mode = match mode {
+            None => 0,
+            Some(TrackingMode::Sidereal) if self.southern => 3,
+            Some(TrackingMode::Sidereal) => 2,
+            Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "the mount only tracks at the sidereal rate"))
+        };
+
+        self.query(&[b'T', mode]).map(|_| ())
+    }
+
+    /// Stops the tracking, then slews to the position
+    fn park(&mut self, position: ParkPosition) -> io::Result<()> {
+        self.track(None)?;
+
+        let azimuth = to_fraction(position.azimuth.to_seconds() / TURN_SECONDS);
+        let altitude = to_fraction(position.altitude.to_seconds() / TURN_SECONDS);
+        self.query(format!("b{:08X},{:08X}", azimuth, altitude).as_bytes())?;
+        self.parked = true;
+        Ok(())
+    }
+
+    fn unpark(&mut self) -> io::Result<()> {
+        self.track(Some(TrackingMode::Sidereal))?;
+        self.parked = false;
+        Ok(())
+    }
+
+    /// Cancels the goto in progress, then stops both motors
+    fn abort(&mut self) -> io::Result<()> {
+        self.query(b"M")?;
+        self.move_axis(Direction::Up, 0.0)?;
+        self.move_axis(Direction::Left, 0.0)
+    }
+
+    /// Starts (or stops, with a null rate) moving an axis, `rate` being in degrees per second
+    fn move_axis(&mut self, dir: Direction, rate: f64) -> io::Result<()> {
+        let (motor, direction) = match dir {
+            Direction::Up => (ALT_DEC_MOTOR, POSITIVE_VARIABLE_RATE),
+            Direction::Down => (ALT_DEC_MOTOR, NEGATIVE_VARIABLE_RATE),
+            Direction::Left => (AZM_RA_MOTOR, POSITIVE_VARIABLE_RATE),
+            Direction::Right => (AZM_RA_MOTOR, NEGATIVE_VARIABLE_RATE)
+        };
+        // the motor controllers count in quarters of second of arc
+        let [high, low] = ((rate * 3600.0 * 4.0).round() as u16).to_be_bytes();
+
+        self.query(&[b'P', 3, motor, direction, high, low, 0, 0]).map(|_| ())
+    }
+}
+
+impl<P: Read + Write> Backend for NexStar<P> {
+    fn handle(&mut self, event: &UserEvent) -> io::Result<()> {
+        match *event {
+            UserEvent::Rotate(right_asc, decl) => self.slew(right_asc, decl),
+            UserEvent::Sync(right_asc, decl) => self.sync(right_asc, decl),
+            UserEvent::BeginMove(dir, rate) => self.move_axis(dir, rate.to_degrees_per_second(MAX_MOVE_RATE)),
+            UserEvent::EndMove(dir) => self.move_axis(dir, 0.0),
+            UserEvent::Position(longitude, latitude) => self.set_location(longitude, latitude),
+            UserEvent::Zero | UserEvent::KeepMoving => Ok(()),
+            UserEvent::Abort => self.abort(),
+            UserEvent::Track(mode) => self.track(mode),
+            UserEvent::Park(position) => self.park(position),
+            UserEvent::Unpark => self.unpark(),
+            UserEvent::FindHome => Err(io::Error::new(io::ErrorKind::InvalidInput, "the mount has no home sensors"))
+        }
+    }
+
+    fn status(&mut self) -> io::Result<MountStatus> {
+        let (right_asc, decl) = self.query_position(b"e")?;
+        let (azimuth, altitude) = self.query_position(b"z")?;
+        let slewing = self.query(b"L")? == b"1";
+        // the tracking mode: 0 when off, else alt-az or equatorial
+        let tracking = self.query(b"t")?.first().map(|&mode| mode != 0).unwrap_or(false);
+
+        Ok(MountStatus {
+            right_asc: Angle::from_seconds(right_asc as f64 / REVOLUTION * DAY_SECONDS),
+            decl: from_fraction(decl),
+            altitude: from_fraction(altitude),
+            azimuth: Angle::from_seconds(azimuth as f64 / REVOLUTION * TURN_SECONDS),
+            tracking,
+            // the only rate of the protocol
+            tracking_mode: if tracking { Some(TrackingMode::Sidereal) } else { None },
+            slewing,
+            parked: self.parked
+        })
+    }
+}
+
+
+/// Angle between -180 and 180° from a fraction of a revolution
+fn from_fraction(position: u32) -> Angle {
+    Angle::from_seconds(position as i32 as f64 / REVOLUTION * TURN_SECONDS)
+}
+
+/// Fraction of a revolution, `turns` being taken modulo 1
+fn to_fraction(turns: f64) -> u32 {
+    (turns.rem_euclid(1.0) * REVOLUTION).round() as u64 as u32
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backends::scripted::ScriptedPort, data::{Sign, SlewRate}};
+
+    #[test]
+    fn slew_and_sync() {
+        let port = ScriptedPort::new()
+            .expect(b"r80000000,20000000", b"#")
+            .expect(b"s40000000,E0000000", b"#");
+        let mut mount = NexStar::new(port);
+
+        mount.handle(&UserEvent::Rotate(Angle(Sign::Positive, 12, 0, 0.0), Angle(Sign::Positive, 45, 0, 0.0))).unwrap();
+        mount.handle(&UserEvent::Sync(Angle(Sign::Positive, 6, 0, 0.0), Angle(Sign::Negative, 45, 0, 0.0))).unwrap();
+        assert!(mount.port.is_done());
+    }
+
+    #[test]
+    fn moves() {
+        let port = ScriptedPort::new()
+            // 3°/s, in quarters of second of arc
+            .expect(&[b'P', 3, ALT_DEC_MOTOR, POSITIVE_VARIABLE_RATE, 0xA8, 0xC0, 0, 0], b"#")
+            // half the sidereal rate: 7.5″/s
+            .expect(&[b'P', 3, AZM_RA_MOTOR, NEGATIVE_VARIABLE_RATE, 0x00, 0x1E, 0, 0], b"#")
+            .expect(&[b'P', 3, ALT_DEC_MOTOR, POSITIVE_VARIABLE_RATE, 0, 0, 0, 0], b"#")
+            .expect(b"M", b"#")
+            .expect(&[b'P', 3, ALT_DEC_MOTOR, POSITIVE_VARIABLE_RATE, 0, 0, 0, 0], b"#")
+            .expect(&[b'P', 3, AZM_RA_MOTOR, POSITIVE_VARIABLE_RATE, 0, 0, 0, 0], b"#");
+        let mut mount = NexStar::new(port);
+
+        mount.handle(&UserEvent::BeginMove(Direction::Up, SlewRate::Max)).unwrap();
+        mount.handle(&UserEvent::BeginMove(Direction::Right, SlewRate::Guide)).unwrap();
+        mount.handle(&UserEvent::EndMove(Direction::Up)).unwrap();
+        mount.handle(&UserEvent::Abort).unwrap();
+        assert!(mount.port.is_done());
+    }
+
+    #[test]
+    fn location_and_tracking() {
+        let port = ScriptedPort::new()
+            .expect(&[b'W', 48, 51, 0, 0, 2, 21, 0, 0], b"#")
+            .expect(&[b'T', 2], b"#")
+            .expect(&[b'W', 33, 27, 0, 1, 70, 30, 0, 1], b"#")
+            .expect(&[b'T', 3], b"#")
+            .expect(&[b'T', 0], b"#");
+        let mut mount = NexStar::new(port);
+
+        mount.handle(&UserEvent::Position(Angle(Sign::Positive, 2, 21, 0.0), Angle(Sign::Positive, 48, 51, 0.0))).unwrap();
+        mount.handle(&UserEvent::Track(Some(TrackingMode::Sidereal))).unwrap();
+        // reversed in the southern hemisphere
+        mount.handle(&UserEvent::Position(Angle(Sign::Negative, 70, 30, 0.0), Angle(Sign::Negative, 33, 27, 0.0))).unwrap();
+        mount.handle(&UserEvent::Track(Some(TrackingMode::Sidereal))).unwrap();
+        assert!(mount.handle(&UserEvent::Track(Some(TrackingMode::Lunar))).is_err());
+        mount.handle(&UserEvent::Track(None)).unwrap();
+        assert!(mount.port.is_done());
+    }
+
+    #[test]
+    fn park() {
+        let port = ScriptedPort::new()
+            .expect(&[b'T', 0], b"#")
+            .expect(b"b80000000,00000000", b"#")
+            .expect(b"e", b"80000000,E0000000#")
+            .expect(b"z", b"40000000,10000000#")
+            .expect(b"L", b"0#")
+            .expect(b"t", &[0, b'#'])
+            .expect(&[b'T', 2], b"#");
+        let mut mount = NexStar::new(port);
+
+        mount.handle(&UserEvent::Park(ParkPosition { altitude: Angle::default(), azimuth: Angle(Sign::Positive, 180, 0, 0.0) })).unwrap();
+        let status = mount.status().unwrap();
+        assert!(status.parked && !status.tracking && !status.slewing);
+        assert_eq!(status.tracking_mode, None);
+        mount.handle(&UserEvent::Unpark).unwrap();
+        assert!(!mount.parked);
+        assert!(mount.handle(&UserEvent::FindHome).is_err());
+        assert!(mount.port.is_done());
+    }
+
+    #[test]
+    fn status() {
+        let port = ScriptedPort::new()
+            .expect(b"e", b"80000000,E0000000#")
+            .expect(b"z", b"40000000,10000000#")
+            .expect(b"L", b"1#")
+            .expect(b"t", &[2, b'#']);
+        let mut mount = NexStar::new(port);
+
+        assert_eq!(mount.status().unwrap(), MountStatus {
+            right_asc: Angle(Sign::Positive, 12, 0, 0.0),
+            decl: Angle(Sign::Negative, 45, 0, 0.0),
+            altitude: Angle(Sign::Positive, 22, 30, 0.0),
+            azimuth: Angle(Sign::Positive, 90, 0, 0.0),
+            tracking: true,
+            tracking_mode: Some(TrackingMode::Sidereal),
+            slewing: true,
+            parked: false
+        });
+        assert!(mount.port.is_done());
+    }
+}
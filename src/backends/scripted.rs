@@ -0,0 +1,81 @@
+//! Scripted serial port, to test the serial backends without hardware.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+
+/// Stand-in for a serial port, replaying a script: every command expected to be written
+/// is answered with its reply.
+#[derive(Default)]
+pub struct ScriptedPort {
+    script: VecDeque<(Vec<u8>, Vec<u8>)>,
+
+    /// bytes written which do not form a complete command yet
+    written: Vec<u8>,
+    /// bytes of the replies not read yet
+    replies: VecDeque<u8>
+}
+
+impl ScriptedPort {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a command to the script, which is answered with `reply` once written
+    pub fn expect(mut self, command: &[u8], reply: &[u8]) -> Self {
+        self.script.push_back((command.to_vec(), reply.to_vec()));
+        self
+    }
+
+    /// true if every command of the script was written and every reply was read
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty() && self.written.is_empty() && self.replies.is_empty()
+    }
+}
+
+impl Write for ScriptedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+
+        while let Some((command, reply)) = self.script.front() {
+            if self.written.len() < command.len() {
+                if !command.starts_with(&self.written) {
+                    break;
+                }
+                return Ok(buf.len());
+            }
+            if !self.written.starts_with(command) {
+                break;
+            }
+
+            self.written.drain(..command.len());
+            self.replies.extend(reply);
+            self.script.pop_front();
+        }
+
+        if self.written.is_empty() {
+            Ok(buf.len())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected command {:?}", self.written)))
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for ScriptedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.replies.is_empty() {
+            // what a serial port does when the other end does not answer
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no reply in the script"));
+        }
+
+        let n = buf.len().min(self.replies.len());
+        for (byte, reply) in buf.iter_mut().zip(self.replies.drain(..n)) {
+            *byte = reply;
+        }
+        Ok(n)
+    }
+}
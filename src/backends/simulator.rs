@@ -0,0 +1,413 @@
+//! Virtual equatorial mount, for demos and for working on the window without hardware.
+//!
+//! The pointing moves with the configured rates and acceleration. It follows the real time
+//! when driven by `run`, or advances only through `step` when `realtime` is false, which
+//! makes it deterministic.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::{
+    backends::Backend,
+    data::{Angle, AngleKind, Unit, Direction, SlewRate, TrackingMode, MountStatus, SIDEREAL_RATE, horizontal_to_equatorial, equatorial_to_horizontal},
+    events::UserEvent
+};
+
+
+/// Distance below which a slew is considered done, in degrees
+const ARRIVAL: f64 = 1.0 / 3600.0;
+
+
+/// Characteristics of the simulated mount. Rates are in degrees per second.
+#[derive(Clone, Debug)]
+pub struct SimulatorConfig {
+    /// Maximal rate of the slews
+    pub slew_rate: f64,
+    /// Fastest rate of the manual moves
+    pub move_rate: f64,
+    /// Acceleration of both axes, in degrees per second squared
+    pub acceleration: f64,
+    /// true if the mount compensates the rotation of the sky when the simulation starts
+    pub tracking: bool,
+    /// Range of declinations the mount can reach, in degrees
+    pub min_decl: f64,
+    pub max_decl: f64,
+    /// Local sidereal time when the simulation starts
+    pub sidereal_time: Angle,
+    /// true if the simulation follows the real time, false if it only advances with `step`
+    pub realtime: bool
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        SimulatorConfig {
+            slew_rate: 4.0,
+            move_rate: 3.0,
+            acceleration: 2.0,
+            tracking: true,
+            min_decl: -90.0,
+            max_decl: 90.0,
+            sidereal_time: Angle::default(),
+            realtime: true
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Motion {
+    Idle,
+    /// constant rate, signed
+    Manual(f64),
+    /// slew to a position
+    Goto(f64)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Axis {
+    position: f64,
+    velocity: f64,
+    motion: Motion,
+    /// true if the positions are taken modulo 360°
+    circular: bool
+}
+
+impl Axis {
+    fn new(position: f64, circular: bool) -> Self {
+        Axis { position, velocity: 0.0, motion: Motion::Idle, circular }
+    }
+
+    fn distance_to(&self, target: f64) -> f64 {
+        let distance = target - self.position;
+        if self.circular {
+            (distance + 180.0).rem_euclid(360.0) - 180.0
+        } else {
+            distance
+        }
+    }
+
+    fn step(&mut self, dt: f64, config: &SimulatorConfig) {
+        let wanted = match self.motion {
+            Motion::Idle => 0.0,
+            Motion::Manual(rate) => rate,
+            // as fast as possible, while still being able to stop at the target
+            Motion::Goto(target) => {
+                let distance = self.distance_to(target);
+                distance.signum() * config.slew_rate.min((2.0 * config.acceleration * distance.abs()).sqrt())
+            }
+        };
+
+        let change = config.acceleration * dt;
+        self.velocity += (wanted - self.velocity).clamp(-change, change);
+        self.position += self.velocity * dt;
+
+        if let Motion::Goto(target) = self.motion {
+            let distance = self.distance_to(target);
+            // arrived, or passed the target during the step
+            if distance.abs() < ARRIVAL || distance.signum() != wanted.signum() {
+                self.position = target;
+                self.velocity = 0.0;
+                self.motion = Motion::Idle;
+            }
+        }
+
+        if self.circular {
+            self.position = self.position.rem_euclid(360.0);
+        }
+    }
+}
+
+
+pub struct Simulator {
+    config: SimulatorConfig,
+
+    /// right ascension, in degrees
+    right_asc: Axis,
+    decl: Axis,
+    /// local sidereal time, in degrees
+    sidereal_time: f64,
+    tracking: bool,
+    tracking_mode: TrackingMode,
+    /// true if the mount refuses to move until it is unparked
+    parked: bool,
+    /// latitude of the site, in degrees
+    latitude: f64,
+    longitude: f64,
+
+    last_update: Instant
+}
+
+impl Simulator {
+    /// Builds a mount pointing at the celestial pole, with the site at the origin of the coordinates
+    pub fn new(config: SimulatorConfig) -> Self {
+        let sidereal_time = config.sidereal_time.to_degrees(Unit::Hours);
+        let decl = 90f64.clamp(config.min_decl, config.max_decl);
+        let tracking = config.tracking;
+
+        Simulator {
+            config,
+            right_asc: Axis::new(sidereal_time, true),
+            decl: Axis::new(decl, false),
+            sidereal_time,
+            tracking,
+            tracking_mode: TrackingMode::default(),
+            parked: false,
+            latitude: 0.0,
+            longitude: 0.0,
+            last_update: Instant::now()
+        }
+    }
+
+    /// Advances the simulation by `dt`
+    pub fn step(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f64();
+
+        self.sidereal_time = (self.sidereal_time + SIDEREAL_RATE * dt).rem_euclid(360.0);
+        self.right_asc.step(dt, &self.config);
+        self.decl.step(dt, &self.config);
+
+        // without tracking, the mount stays still while the sky rotates.
+        // Other rates than the sidereal one drift slowly.
+        let drift = match self.tracking {
+            true => SIDEREAL_RATE - self.tracking_mode.rate() / 3600.0,
+            false => SIDEREAL_RATE
+        };
+        self.right_asc.position = (self.right_asc.position + drift * dt).rem_euclid(360.0);
+
+        // the mechanical limits stop any motion
+        let (min, max) = (self.config.min_decl, self.config.max_decl);
+        if self.decl.position < min || self.decl.position > max {
+            self.decl.position = self.decl.position.clamp(min, max);
+            self.decl.velocity = 0.0;
+            self.decl.motion = Motion::Idle;
+        }
+    }
+
+    /// Current state of the simulated mount, without advancing the simulation
+    pub fn pointing(&self) -> MountStatus {
+        let (altitude, azimuth) = self.horizontal();
+
+        MountStatus {
+            right_asc: AngleKind::RightAscension.normalize(Angle::from_degrees(self.right_asc.position, Unit::Hours)),
+            decl: Angle::from_decimal(self.decl.position),
+            altitude,
+            azimuth,
+            tracking: self.tracking,
+            tracking_mode: Some(self.tracking_mode),
+            slewing: matches!(self.right_asc.motion, Motion::Goto(_)) || matches!(self.decl.motion, Motion::Goto(_)),
+            parked: self.parked
+        }
+    }
+
+    /// Altitude and azimuth (from the north, eastward) of the pointing
+    fn horizontal(&self) -> (Angle, Angle) {
+        let hour_angle = Angle::from_degrees(self.sidereal_time - self.right_asc.position, Unit::Hours);
+        equatorial_to_horizontal(hour_angle, Angle::from_decimal(self.decl.position), Angle::from_decimal(self.latitude))
+    }
+
+    /// Advances the simulation up to now, if it follows the real time
+    fn catch_up(&mut self) {
+        let now = Instant::now();
+        if self.config.realtime {
+            self.step(now - self.last_update);
+        }
+        self.last_update = now;
+    }
+
+    /// Starts moving an axis, or stops it if `rate` is None
+    fn move_axis(&mut self, dir: Direction, rate: Option<SlewRate>) {
+        let (axis, sign) = match dir {
+            Direction::Up => (&mut self.decl, 1.0),
+            Direction::Down => (&mut self.decl, -1.0),
+            Direction::Left => (&mut self.right_asc, 1.0),
+            Direction::Right => (&mut self.right_asc, -1.0)
+        };
+
+        match (rate, axis.motion) {
+            (Some(rate), _) => axis.motion = Motion::Manual(sign * rate.to_degrees_per_second(self.config.move_rate)),
+            // only the move in this direction is stopped
+            (None, Motion::Manual(current)) if current.signum() == sign => axis.motion = Motion::Idle,
+            _ => {}
+        }
+    }
+}
+
+impl Backend for Simulator {
+    fn handle(&mut self, event: &UserEvent) -> io::Result<()> {
+        self.catch_up();
+
+        if self.parked && event.moves() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the mount is parked"));
+        }
+
+        match *event {
+            UserEvent::Rotate(right_asc, decl) => {
+                let decl = decl.to_decimal();
+                if decl < self.config.min_decl || decl > self.config.max_decl {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "declination out of the limits of the mount"));
+                }
+
+                self.right_asc.motion = Motion::Goto(right_asc.to_degrees(Unit::Hours).rem_euclid(360.0));
+                self.decl.motion = Motion::Goto(decl);
+            },
+            // the pointing has no error to correct: the mount simply believes it is there
+            UserEvent::Sync(right_asc, decl) => {
+                self.right_asc.position = right_asc.to_degrees(Unit::Hours).rem_euclid(360.0);
+                self.decl.position = decl.to_decimal().clamp(self.config.min_decl, self.config.max_decl);
+            },
+            UserEvent::BeginMove(dir, rate) => self.move_axis(dir, Some(rate)),
+            UserEvent::EndMove(dir) => self.move_axis(dir, None),
+            UserEvent::Position(longitude, latitude) => {
+                // the local sidereal time follows the longitude
+                let longitude = longitude.to_decimal();
+                self.sidereal_time = (self.sidereal_time + longitude - self.longitude).rem_euclid(360.0);
+                self.longitude = longitude;
+                self.latitude = latitude.to_decimal();
+            },
+            // back to the pole
+            UserEvent::Zero => {
+                self.right_asc.motion = Motion::Goto(self.sidereal_time);
+                self.decl.motion = Motion::Goto(90f64.clamp(self.config.min_decl, self.config.max_decl));
+            },
+            UserEvent::KeepMoving => {},
+            // the axes still decelerate
+            UserEvent::Abort => {
+                self.right_asc.motion = Motion::Idle;
+                self.decl.motion = Motion::Idle;
+            },
+            UserEvent::Track(Some(mode)) => {
+                self.tracking = true;
+                self.tracking_mode = mode;
+            },
+            UserEvent::Track(None) => self.tracking = false,
+            UserEvent::Park(position) => {
+                let (hour_angle, decl) = horizontal_to_equatorial(position.altitude, position.azimuth, Angle::from_decimal(self.latitude));
+                let decl = decl.to_decimal();
+                if decl < self.config.min_decl || decl > self.config.max_decl {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "park position out of the limits of the mount"));
+                }
+
+                // without tracking, the target keeps its hour angle
+                let right_asc = self.sidereal_time - hour_angle.to_degrees(Unit::Hours);
+                self.right_asc.motion = Motion::Goto(right_asc.rem_euclid(360.0));
+                self.decl.motion = Motion::Goto(decl);
+                self.tracking = false;
+                self.parked = true;
+            },
+            UserEvent::Unpark => {
+                self.tracking = true;
+                self.parked = false;
+            },
+            // the encoders are absolute: home is the pole
+            UserEvent::FindHome => {
+                self.right_asc.motion = Motion::Goto(self.sidereal_time);
+                self.decl.motion = Motion::Goto(90f64.clamp(self.config.min_decl, self.config.max_decl));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn status(&mut self) -> io::Result<MountStatus> {
+        self.catch_up();
+        Ok(self.pointing())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::fixtures::assert_close;
+
+    /// Mount which only moves through `run_for`, at a site of latitude 45°N
+    fn simulator() -> Simulator {
+        let mut simulator = Simulator::new(SimulatorConfig { realtime: false, min_decl: -30.0, ..Default::default() });
+        simulator.handle(&UserEvent::Position(Angle::default(), Angle::from_decimal(45.0))).unwrap();
+        simulator
+    }
+
+    fn run_for(simulator: &mut Simulator, seconds: u64) {
+        for _ in 0..seconds * 10 {
+            simulator.step(Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        let mut simulator = simulator();
+
+        simulator.handle(&UserEvent::Rotate(Angle::from_decimal(6.0), Angle::from_decimal(20.0))).unwrap();
+        assert!(simulator.status().unwrap().slewing);
+        run_for(&mut simulator, 60);
+        let status = simulator.status().unwrap();
+        assert!(!status.slewing);
+        assert_close(status.right_asc, 6.0, 1e-6);
+        assert_close(status.decl, 20.0, 1e-6);
+
+        // below the limit of the mount
+        assert!(simulator.handle(&UserEvent::Rotate(Angle::from_decimal(6.0), Angle::from_decimal(-40.0))).is_err());
+
+        simulator.handle(&UserEvent::Sync(Angle::from_decimal(5.5), Angle::from_decimal(-20.0))).unwrap();
+        let status = simulator.status().unwrap();
+        assert_close(status.right_asc, 5.5, 1e-6);
+        assert_close(status.decl, -20.0, 1e-6);
+
+        simulator.handle(&UserEvent::Zero).unwrap();
+        run_for(&mut simulator, 120);
+        let status = simulator.status().unwrap();
+        assert_close(status.decl, 90.0, 1e-6);
+        // the pole is as high as the latitude
+        assert_close(status.altitude, 45.0, 1e-3);
+    }
+
+    #[test]
+    fn position() {
+        let mut simulator = simulator();
+
+        // the local sidereal time is 2h at 30°E: this points at the meridian, above the south
+        simulator.handle(&UserEvent::Position(Angle::from_decimal(30.0), Angle::from_decimal(45.0))).unwrap();
+        simulator.handle(&UserEvent::Rotate(Angle::from_decimal(2.0), Angle::default())).unwrap();
+        run_for(&mut simulator, 60);
+        let status = simulator.status().unwrap();
+        assert_close(status.altitude, 45.0, 0.5);
+        assert_close(status.azimuth, 180.0, 0.5);
+
+        // the same point seen from the southern hemisphere is above the north
+        simulator.handle(&UserEvent::Position(Angle::from_decimal(30.0), Angle::from_decimal(-45.0))).unwrap();
+        let status = simulator.status().unwrap();
+        assert_close(status.altitude, 45.0, 0.5);
+        assert!(status.azimuth.to_decimal() < 0.5 || status.azimuth.to_decimal() > 359.5, "{}", status.azimuth);
+    }
+
+    #[test]
+    fn pad() {
+        let mut simulator = simulator();
+        simulator.handle(&UserEvent::Sync(Angle::default(), Angle::default())).unwrap();
+
+        // 1.5s to reach 3°/s (2.4° by steps of 0.1s), then 8.5s at full rate
+        simulator.handle(&UserEvent::BeginMove(Direction::Up, SlewRate::Max)).unwrap();
+        run_for(&mut simulator, 10);
+        assert_close(simulator.status().unwrap().decl, 27.9, 0.01);
+
+        // stopping a move in another direction has no effect
+        simulator.handle(&UserEvent::EndMove(Direction::Down)).unwrap();
+        run_for(&mut simulator, 1);
+        assert_close(simulator.status().unwrap().decl, 30.9, 0.01);
+
+        // 1.5s to stop, over 2.1°
+        simulator.handle(&UserEvent::EndMove(Direction::Up)).unwrap();
+        run_for(&mut simulator, 5);
+        assert_close(simulator.status().unwrap().decl, 33.0, 0.01);
+
+        // eastward: the right ascension increases, by 30° = 2h
+        simulator.handle(&UserEvent::BeginMove(Direction::Left, SlewRate::Max)).unwrap();
+        run_for(&mut simulator, 10);
+        simulator.handle(&UserEvent::EndMove(Direction::Left)).unwrap();
+        run_for(&mut simulator, 5);
+        let status = simulator.status().unwrap();
+        assert_close(status.right_asc, 2.0, 0.01);
+        assert_close(status.decl, 33.0, 0.01);
+        assert!(!status.slewing);
+    }
+}
@@ -9,7 +9,10 @@ use orbtk::{
     theming::Theme,
 };
 
-use crate::events::UserEvent;
+use crate::{
+    events::{Command, BackendEvent},
+    alignment::SharedModel
+};
 
 /// The `Application` represents the entry point of an OrbTk based application.
 pub struct CustomApplication {
@@ -51,13 +54,20 @@ impl CustomApplication {
     }
 
     /// Creates a new window and add it to the application.
-    pub fn window<F: Fn(&mut BuildContext) -> Entity + 'static>(mut self, sender: mpsc::Sender<UserEvent>, create_fn: F) -> Self {
+    pub fn window<F: Fn(&mut BuildContext) -> Entity + 'static>(
+        mut self,
+        sender: mpsc::Sender<Command>,
+        status_receiver: mpsc::Receiver<BackendEvent>,
+        alignment: SharedModel,
+        create_fn: F) -> Self {
         let (adapter, settings, receiver) = custom_create_window(
             self.name.clone(),
             self.theme.clone(),
             self.request_sender.clone(),
             create_fn,
-            sender
+            sender,
+            status_receiver,
+            alignment
         );
 
         self.shell
@@ -81,7 +91,9 @@ pub fn custom_create_window<F: Fn(&mut BuildContext) -> Entity + 'static>(
     theme: Theme,
     request_sender: mpsc::Sender<ShellRequest<WindowAdapter>>,
     create_fn: F,
-    user_sender: mpsc::Sender<UserEvent>
+    user_sender: mpsc::Sender<Command>,
+    status_receiver: mpsc::Receiver<BackendEvent>,
+    alignment: SharedModel
 ) -> (WindowAdapter, WindowSettings, mpsc::Receiver<WindowRequest>) {
     let app_name = app_name.into();
     let mut world: World<Tree, StringComponentStore, RenderContext2D> =
@@ -105,6 +117,15 @@ pub fn custom_create_window<F: Fn(&mut BuildContext) -> Entity + 'static>(
         .borrow_mut()
         .register("sender", user_sender);
 
+    // taken by the MainView, which forwards the events of the backend as messages
+    registry
+        .borrow_mut()
+        .register("receiver", Some(status_receiver));
+
+    registry
+        .borrow_mut()
+        .register("alignment", alignment);
+
     let context_provider = ContextProvider::new(sender, request_sender, app_name);
 
     let window = {
@@ -1,7 +1,620 @@
-#[derive(Default, Copy, Clone, Debug, PartialEq)]
-pub struct Angle(pub i16, pub u8, pub u8);
+use std::{error, fmt, ops};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sign {
+    #[default]
+    Positive,
+    Negative
+}
+
+impl Sign {
+    pub fn opposite(self) -> Sign {
+        match self {
+            Sign::Positive => Sign::Negative,
+            Sign::Negative => Sign::Positive
+        }
+    }
+}
+
+/// Sexagesimal angle: sign, then degrees (or hours), minutes and seconds.
+/// The sign is kept apart so that e.g. -0°30' can be represented.
+/// The seconds are fractional, between 0 and 60 excluded.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Angle(pub Sign, pub u16, pub u8, pub f64);
+
+impl Angle {
+    /// Builds an angle from a number of seconds (of arc or of time).
+    /// Microseconds are the finest resolution: below, the differences come from rounding errors.
+    pub fn from_seconds(seconds: f64) -> Angle {
+        let magnitude = (seconds.abs() * 1e6).round() / 1e6;
+        let minutes = (magnitude / 60.0).floor();
+        Angle(
+            if seconds < 0.0 { Sign::Negative } else { Sign::Positive },
+            (minutes / 60.0).floor() as u16,
+            (minutes % 60.0) as u8,
+            (magnitude - minutes * 60.0).clamp(0.0, 60.0)
+        )
+    }
+
+    /// Total number of seconds (of arc or of time)
+    pub fn to_seconds(&self) -> f64 {
+        let seconds = self.1 as f64 * 3600.0 + self.2 as f64 * 60.0 + self.3;
+        match self.0 {
+            Sign::Positive => seconds,
+            Sign::Negative => -seconds
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 == Sign::Negative && self.to_seconds() != 0.0
+    }
+
+    /// Same angle, with the seconds rounded to `decimals` decimals
+    pub fn round(&self, decimals: usize) -> Angle {
+        let scale = 10f64.powi(decimals as i32);
+        let magnitude = Angle::from_seconds((self.to_seconds().abs() * scale).round() / scale);
+        Angle(self.0, magnitude.1, magnitude.2, magnitude.3)
+    }
+
+    /// Builds an angle from a decimal value, in the same unit
+    pub fn from_decimal(value: f64) -> Angle {
+        Angle::from_seconds(value * 3600.0)
+    }
+
+    /// Decimal value, in the same unit
+    pub fn to_decimal(&self) -> f64 {
+        self.to_seconds() / 3600.0
+    }
+
+    /// Builds an angle expressed in `unit` from a number of degrees
+    pub fn from_degrees(degrees: f64, unit: Unit) -> Angle {
+        Angle::from_decimal(degrees / unit.degrees())
+    }
+
+    /// Number of degrees, the angle being expressed in `unit`
+    pub fn to_degrees(&self, unit: Unit) -> f64 {
+        self.to_decimal() * unit.degrees()
+    }
+
+    /// Builds an angle expressed in `unit` from a number of hours
+    pub fn from_hours(hours: f64, unit: Unit) -> Angle {
+        Angle::from_degrees(hours * Unit::Hours.degrees(), unit)
+    }
+
+    /// Number of hours, the angle being expressed in `unit`
+    pub fn to_hours(&self, unit: Unit) -> f64 {
+        self.to_degrees(unit) / Unit::Hours.degrees()
+    }
+
+    /// Builds an angle expressed in `unit` from a number of radians
+    pub fn from_radians(radians: f64, unit: Unit) -> Angle {
+        Angle::from_degrees(radians.to_degrees(), unit)
+    }
+
+    /// Number of radians, the angle being expressed in `unit`
+    pub fn to_radians(&self, unit: Unit) -> f64 {
+        self.to_degrees(unit).to_radians()
+    }
+
+    /// Expresses in `to` an angle expressed in `from`
+    pub fn convert(&self, from: Unit, to: Unit) -> Angle {
+        Angle::from_degrees(self.to_degrees(from), to)
+    }
+
+    /// Sexagesimal notation with `decimals` decimals to the seconds, e.g. "12h30m15.2s" or "-05°12'33\""
+    pub fn to_sexagesimal(&self, unit: Unit, decimals: usize) -> String {
+        let angle = self.round(decimals);
+        let sign = if angle.is_negative() { "-" } else { "" };
+        // the width of the seconds includes the decimals and their point
+        let width = if decimals > 0 { decimals + 3 } else { 2 };
+        match unit {
+            Unit::Degrees => format!("{}{:02}°{:02}'{:0w$.d$}\"", sign, angle.1, angle.2, angle.3, w = width, d = decimals),
+            Unit::Hours => format!("{}{:02}h{:02}m{:0w$.d$}s", sign, angle.1, angle.2, angle.3, w = width, d = decimals)
+        }
+    }
+}
+
+/// Unit of the degrees (or hours) of an angle
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Degrees,
+    Hours
+}
+
+impl Unit {
+    /// Number of degrees in one unit
+    fn degrees(self) -> f64 {
+        match self {
+            Unit::Degrees => 1.0,
+            Unit::Hours => 15.0
+        }
+    }
+}
+
+/// `value` modulo `modulus`, between 0 and `modulus` excluded
+fn modulo(value: f64, modulus: f64) -> f64 {
+    let result = value.rem_euclid(modulus);
+    // rounding makes tiny negative values give `modulus`
+    if result < modulus { result } else { 0.0 }
+}
+
+impl ops::Add for Angle {
+    type Output = Angle;
+
+    fn add(self, other: Angle) -> Angle {
+        Angle::from_seconds(self.to_seconds() + other.to_seconds())
+    }
+}
+
+impl ops::Sub for Angle {
+    type Output = Angle;
+
+    fn sub(self, other: Angle) -> Angle {
+        Angle::from_seconds(self.to_seconds() - other.to_seconds())
+    }
+}
+
+impl ops::Neg for Angle {
+    type Output = Angle;
+
+    fn neg(self) -> Angle {
+        Angle(self.0.opposite(), self.1, self.2, self.3)
+    }
+}
+
+/// Angle between two points of a sphere, given by their longitudes (or right ascensions)
+/// expressed in `unit` and their latitudes (or declinations) in degrees.
+/// The separation is in degrees.
+pub fn separation(first: (Angle, Angle), second: (Angle, Angle), unit: Unit) -> Angle {
+    let (lon1, lat1) = (first.0.to_radians(unit), first.1.to_radians(Unit::Degrees));
+    let (lon2, lat2) = (second.0.to_radians(unit), second.1.to_radians(Unit::Degrees));
+    let delta = lon2 - lon1;
+
+    // Vincenty formula, accurate for both small and large separations
+    let y = ((lat2.cos() * delta.sin()).powi(2)
+        + (lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta.cos()).powi(2)).sqrt();
+    let x = lat1.sin() * lat2.sin() + lat1.cos() * lat2.cos() * delta.cos();
+
+    Angle::from_radians(y.atan2(x), Unit::Degrees)
+}
+
+/// Hour angle (westward, in hours) and declination (in degrees) of the point at `altitude`
+/// and `azimuth` (from the north, eastward), seen from `latitude`
+pub fn horizontal_to_equatorial(altitude: Angle, azimuth: Angle, latitude: Angle) -> (Angle, Angle) {
+    let altitude = altitude.to_radians(Unit::Degrees);
+    let azimuth = azimuth.to_radians(Unit::Degrees);
+    let latitude = latitude.to_radians(Unit::Degrees);
+
+    let decl = (latitude.sin() * altitude.sin() + latitude.cos() * altitude.cos() * azimuth.cos()).asin();
+    let hour_angle = (-altitude.cos() * azimuth.sin())
+        .atan2(altitude.sin() * latitude.cos() - altitude.cos() * azimuth.cos() * latitude.sin());
+
+    (
+        AngleKind::RightAscension.normalize(Angle::from_radians(hour_angle, Unit::Hours)),
+        Angle::from_radians(decl, Unit::Degrees)
+    )
+}
+
+/// Altitude and azimuth (from the north, eastward) of the point at `hour_angle` (westward, in hours)
+/// and `decl`, seen from `latitude`
+pub fn equatorial_to_horizontal(hour_angle: Angle, decl: Angle, latitude: Angle) -> (Angle, Angle) {
+    let hour_angle = hour_angle.to_radians(Unit::Hours);
+    let decl = decl.to_radians(Unit::Degrees);
+    let latitude = latitude.to_radians(Unit::Degrees);
+
+    let altitude = (latitude.sin() * decl.sin() + latitude.cos() * decl.cos() * hour_angle.cos()).asin();
+    let azimuth = (-decl.cos() * hour_angle.sin())
+        .atan2(decl.sin() * latitude.cos() - decl.cos() * hour_angle.cos() * latitude.sin());
+
+    (
+        Angle::from_radians(altitude, Unit::Degrees),
+        AngleKind::Azimuth.normalize(Angle::from_radians(azimuth, Unit::Degrees))
+    )
+}
+
+/// Greenwich mean sidereal time at `time`, in hours, precise to about a second over this century
+pub fn greenwich_sidereal_time(time: SystemTime) -> Angle {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64()
+    };
+    // days since J2000.0 (2000-01-01 12:00 UT)
+    let days = seconds / 86_400.0 - 10_957.5;
+
+    let hours = 18.697_374_558 + 24.065_709_824_419_08 * days;
+    // reduced first: the hours of an angle are bounded
+    AngleKind::RightAscension.normalize(Angle::from_decimal(hours.rem_euclid(24.0)))
+}
+
+/// Local sidereal time at `time`, in hours, at `longitude` (in degrees, eastward)
+pub fn local_sidereal_time(time: SystemTime, longitude: Angle) -> Angle {
+    let hours = greenwich_sidereal_time(time).to_decimal() + longitude.to_decimal() / 15.0;
+    AngleKind::RightAscension.normalize(Angle::from_decimal(hours))
+}
+
+/// Written in degrees, the precision giving the decimals of the seconds:
+/// e.g. "-05°12'33\"" or, with `{:.1}`, "-05°12'33.0\""
+impl fmt::Display for Angle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_sexagesimal(Unit::Degrees, f.precision().unwrap_or(0)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseAngleError(String);
+
+impl fmt::Display for ParseAngleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid angle \"{}\"", self.0)
+    }
+}
+
+impl error::Error for ParseAngleError {}
+
+impl FromStr for Angle {
+    type Err = ParseAngleError;
+
+    fn from_str(text: &str) -> Result<Angle, ParseAngleError> {
+        parse_sexagesimal(text).map(|(angle, _)| angle)
+    }
+}
+
+/// Parses an angle such as "12h30m15.2s", "-05°12'33\"", "+22° 00′ 52.2″", "+45:30:00", "-5 12 33" or "12.5".
+/// Returns the unit too when the text gives it ('h', or '°' and 'd').
+pub fn parse_sexagesimal(text: &str) -> Result<(Angle, Option<Unit>), ParseAngleError> {
+    let invalid = || ParseAngleError(text.to_string());
+
+    let trimmed = text.trim();
+    let (sign, rest) = match trimmed.strip_prefix(&['-', '−'][..]) {
+        Some(rest) => (Sign::Negative, rest),
+        None => (Sign::Positive, trimmed.strip_prefix('+').unwrap_or(trimmed))
+    };
+
+    let mut unit = None;
+    let mut fields: Vec<f64> = vec![];
+    let mut number = String::new();
+
+    for c in rest.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+
+        let separator = match c {
+            'h' | 'H' => { unit = Some(Unit::Hours); true },
+            // 'º' is often used in place of '°'
+            '°' | 'º' | 'd' | 'D' => { unit = Some(Unit::Degrees); true },
+            // primes, and the quotes which replace them in many texts
+            'm' | 'M' | 's' | 'S' | '\'' | '"' | '′' | '″' | '’' | '”' | ':' => true,
+            _ => c.is_whitespace()
+        };
+        if !separator {
+            return Err(invalid());
+        }
+
+        if !number.is_empty() {
+            fields.push(number.parse().map_err(|_| invalid())?);
+            number.clear();
+        }
+    }
+
+    // only the last field may be fractional, the minutes and seconds being below 60
+    let last = fields.len().checked_sub(1).ok_or_else(invalid)?;
+    let valid = fields.len() <= 3
+        && fields.iter().enumerate().all(|(i, field)| {
+            (i == last || field.fract() == 0.0) && (i == 0 || *field < 60.0)
+        });
+    if !valid {
+        return Err(invalid());
+    }
+
+    let seconds = fields.iter().zip([3600.0, 60.0, 1.0]).map(|(field, scale)| field * scale).sum::<f64>();
+    let magnitude = Angle::from_seconds(seconds);
+
+    Ok((Angle(sign, magnitude.1, magnitude.2, magnitude.3), unit))
+}
+
+/// What an angle measures, which gives its unit and its range
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AngleKind {
+    /// 0 to 24h, wraps
+    #[default]
+    RightAscension,
+    /// -90 to 90°, clamps
+    Declination,
+    /// -90 to 90°, clamps
+    Latitude,
+    /// -180 to 180°, wraps
+    Longitude,
+    /// -90 to 90°, clamps
+    Altitude,
+    /// 0 to 360°, wraps
+    Azimuth
+}
+
+impl AngleKind {
+    /// Symbol following the degrees or hours
+    pub fn suffix(self) -> &'static str {
+        match self.unit() {
+            Unit::Hours => "h",
+            Unit::Degrees => "°"
+        }
+    }
+
+    pub fn unit(self) -> Unit {
+        match self {
+            AngleKind::RightAscension => Unit::Hours,
+            _ => Unit::Degrees
+        }
+    }
+
+    /// true if the angle can be negative
+    pub fn signed(self) -> bool {
+        !matches!(self, AngleKind::RightAscension | AngleKind::Azimuth)
+    }
+
+    /// true if the values out of the range wrap around, false if they are clamped
+    pub fn wraps(self) -> bool {
+        matches!(self, AngleKind::RightAscension | AngleKind::Longitude | AngleKind::Azimuth)
+    }
+
+    /// Bound of the range, in degrees or hours: the angle is within [0, limit) if unsigned,
+    /// else within [-limit, limit]
+    pub fn limit(self) -> u16 {
+        match self {
+            AngleKind::RightAscension => 24,
+            AngleKind::Declination | AngleKind::Latitude | AngleKind::Altitude => 90,
+            AngleKind::Longitude => 180,
+            AngleKind::Azimuth => 360
+        }
+    }
+
+    pub fn contains(self, angle: Angle) -> bool {
+        let limit = self.limit() as f64 * 3600.0;
+        let seconds = angle.to_seconds();
+
+        if self.signed() {
+            -limit <= seconds && seconds <= limit
+        } else {
+            0.0 <= seconds && seconds < limit
+        }
+    }
+
+    /// Brings the angle back within the range, by wrapping it around or clamping it
+    pub fn normalize(self, angle: Angle) -> Angle {
+        if self.contains(angle) {
+            return angle;
+        }
+
+        let limit = self.limit() as f64 * 3600.0;
+        let seconds = angle.to_seconds();
+        Angle::from_seconds(match (self.wraps(), self.signed()) {
+            (true, true) => modulo(seconds + limit, 2.0 * limit) - limit,
+            (true, false) => modulo(seconds, limit),
+            (false, _) => seconds.clamp(-limit, limit)
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     Up, Down, Left, Right
 }
+
+impl Direction {
+    /// Axis moved in this direction: Up and Down move the declination, Left and Right the right ascension
+    pub fn axis(self) -> Axis {
+        match self {
+            Direction::Up | Direction::Down => Axis::Declination,
+            Direction::Left | Direction::Right => Axis::RightAscension
+        }
+    }
+}
+
+/// Rate at which the mount follows the sky
+#[derive(Default, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrackingMode {
+    /// Follows the stars
+    #[default]
+    Sidereal,
+    /// Follows the Moon
+    Lunar,
+    /// Follows the Sun
+    Solar,
+    /// Sidereal rate corrected for the atmospheric refraction, as proposed by King
+    King,
+    /// Rate of the right ascension axis, in seconds of arc per second
+    Custom(f64)
+}
+
+impl TrackingMode {
+    /// Rate of the right ascension axis, in seconds of arc per second
+    pub fn rate(self) -> f64 {
+        match self {
+            TrackingMode::Sidereal => SIDEREAL_RATE * 3600.0,
+            TrackingMode::Lunar => 14.685,
+            TrackingMode::Solar => 15.0,
+            TrackingMode::King => 15.0369,
+            TrackingMode::Custom(rate) => rate
+        }
+    }
+}
+
+/// Axis of an equatorial mount, which can move independently of the other one
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Axis {
+    RightAscension,
+    Declination
+}
+
+/// Rotation of the sky, in degrees per second
+pub const SIDEREAL_RATE: f64 = 360.0 / 86_164.090_5;
+
+/// Speed of the manual moves
+#[derive(Default, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SlewRate {
+    /// Half the sidereal rate, to correct the tracking
+    Guide,
+    /// 8 times the sidereal rate, to center an object in the eyepiece
+    #[default]
+    Centering,
+    /// 64 times the sidereal rate, to look for an object in the finder
+    Find,
+    /// Fastest rate of the mount
+    Max,
+    /// Multiple of the sidereal rate
+    Sidereal(f64)
+}
+
+impl SlewRate {
+    /// Multiple of the sidereal rate, None for the fastest rate of the mount
+    pub fn multiple(self) -> Option<f64> {
+        match self {
+            SlewRate::Guide => Some(0.5),
+            SlewRate::Centering => Some(8.0),
+            SlewRate::Find => Some(64.0),
+            SlewRate::Max => None,
+            SlewRate::Sidereal(multiple) => Some(multiple)
+        }
+    }
+
+    /// Rate in degrees per second, `max` being the fastest rate of the mount
+    pub fn to_degrees_per_second(self, max: f64) -> f64 {
+        self.multiple().map_or(max, |multiple| (multiple * SIDEREAL_RATE).min(max))
+    }
+
+    /// Named rate closest to this one, for the protocols only knowing a few fixed rates
+    pub fn preset(self) -> SlewRate {
+        match self {
+            // halfway between the presets, on a logarithmic scale
+            SlewRate::Sidereal(multiple) if multiple < 2.0 => SlewRate::Guide,
+            SlewRate::Sidereal(multiple) if multiple < 24.0 => SlewRate::Centering,
+            SlewRate::Sidereal(multiple) if multiple < 256.0 => SlewRate::Find,
+            SlewRate::Sidereal(_) => SlewRate::Max,
+            rate => rate
+        }
+    }
+}
+
+/// Position where the mount rests between the nights, fixed relative to the ground
+#[derive(Default, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParkPosition {
+    pub altitude: Angle,
+    /// from the north, eastward
+    pub azimuth: Angle
+}
+
+/// State of the mount, as reported by the backend
+#[derive(Default, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MountStatus {
+    /// Right ascension, in hours
+    pub right_asc: Angle,
+    /// Declination, in degrees
+    pub decl: Angle,
+    /// Altitude above the horizon, in degrees
+    pub altitude: Angle,
+    /// Azimuth, in degrees
+    pub azimuth: Angle,
+
+    /// true if the mount compensates the rotation of the Earth
+    pub tracking: bool,
+    /// Mode of the tracking, whether it is active or not. None if the backend does not report it.
+    pub tracking_mode: Option<TrackingMode>,
+    /// true if the mount is moving towards a target
+    pub slewing: bool,
+    /// true if the mount is parked, and refuses to move until it is unparked
+    pub parked: bool
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(angle: Angle, seconds: f64) {
+        assert!((angle.to_seconds() - seconds).abs() < 1e-6, "{:?} is not {}″", angle, seconds);
+    }
+
+    #[test]
+    fn sexagesimal() {
+        assert_eq!(Angle(Sign::Positive, 12, 30, 15.2).to_sexagesimal(Unit::Hours, 1), "12h30m15.2s");
+        assert_eq!(Angle(Sign::Negative, 5, 12, 33.0).to_sexagesimal(Unit::Degrees, 0), "-05°12'33\"");
+        assert_eq!(Angle(Sign::Negative, 0, 30, 0.0).to_sexagesimal(Unit::Degrees, 0), "-00°30'00\"");
+        // rounded up to the next minute
+        assert_eq!(Angle(Sign::Positive, 1, 59, 59.96).to_sexagesimal(Unit::Hours, 1), "02h00m00.0s");
+    }
+
+    #[test]
+    fn parse_display() {
+        for text in ["-05°12'33\"", "-00°30'00\"", "89°59'59\"", "00°00'00\""].iter() {
+            assert_eq!(text.parse::<Angle>().unwrap().to_string(), *text);
+        }
+        let angle = Angle(Sign::Negative, 0, 30, 12.5);
+        assert_eq!(format!("{:.1}", angle).parse::<Angle>().unwrap(), angle);
+
+        let parse = |text: &str| parse_sexagesimal(text).map(|(angle, unit)| (angle.to_sexagesimal(Unit::Degrees, 1), unit)).unwrap();
+        assert_eq!(parse("12h30m15.2s"), ("12°30'15.2\"".to_string(), Some(Unit::Hours)));
+        assert_eq!(parse("+22° 00′ 52.2″"), ("22°00'52.2\"".to_string(), Some(Unit::Degrees)));
+        assert_eq!(parse("-5 12 33"), ("-05°12'33.0\"".to_string(), None));
+        assert_eq!(parse("12.5"), ("12°30'00.0\"".to_string(), None));
+        for text in ["", "12x", "12 60", "12.5 30", "1 2 3 4"].iter() {
+            assert!(text.parse::<Angle>().is_err(), "{} parsed", text);
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Angle::from_decimal(12.5), Angle(Sign::Positive, 12, 30, 0.0));
+        assert_eq!(Angle::from_decimal(-0.5), Angle(Sign::Negative, 0, 30, 0.0));
+        assert_eq!(Angle(Sign::Negative, 5, 15, 0.0).to_decimal(), -5.25);
+        assert_close(Angle::from_seconds(-18_062.25), -18_062.25);
+
+        assert_close(Angle::from_degrees(90.0, Unit::Hours), 6.0 * 3600.0);
+        assert_eq!(Angle::from_decimal(6.0).to_degrees(Unit::Hours), 90.0);
+        assert_close(Angle::from_hours(6.0, Unit::Degrees), 90.0 * 3600.0);
+        assert_eq!(Angle::from_decimal(90.0).to_hours(Unit::Degrees), 6.0);
+        assert_close(Angle::from_radians(std::f64::consts::PI, Unit::Hours), 12.0 * 3600.0);
+        assert!((Angle::from_decimal(-90.0).to_radians(Unit::Degrees) + std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert_close(Angle::from_decimal(3.0).convert(Unit::Hours, Unit::Degrees), 45.0 * 3600.0);
+    }
+
+    #[test]
+    fn normalize() {
+        let normalize = |kind: AngleKind, degrees: f64| kind.normalize(Angle::from_decimal(degrees));
+
+        assert_close(normalize(AngleKind::RightAscension, -1.0 / 3600.0), 86_399.0);
+        assert_close(normalize(AngleKind::RightAscension, 24.0), 0.0);
+        assert_close(normalize(AngleKind::Azimuth, 370.0), 10.0 * 3600.0);
+        assert_close(normalize(AngleKind::Longitude, 190.0), -170.0 * 3600.0);
+        assert_close(normalize(AngleKind::Longitude, -180.0), -180.0 * 3600.0);
+        assert_close(normalize(AngleKind::Declination, 95.0), 90.0 * 3600.0);
+        assert_close(normalize(AngleKind::Latitude, -95.0), -90.0 * 3600.0);
+        assert_close(normalize(AngleKind::Altitude, 45.0), 45.0 * 3600.0);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let half_degree = Angle(Sign::Positive, 0, 30, 0.0);
+        assert_eq!(half_degree - Angle::from_decimal(1.0), Angle(Sign::Negative, 0, 30, 0.0));
+        assert_eq!(half_degree + Angle(Sign::Negative, 1, 0, 0.0), -half_degree);
+        assert_eq!(Angle(Sign::Positive, 0, 59, 59.5) + Angle(Sign::Positive, 0, 0, 0.5), Angle::from_decimal(1.0));
+        assert!(!(half_degree - half_degree).is_negative());
+    }
+
+    #[test]
+    fn separations() {
+        let (zero, pole) = (Angle::default(), Angle::from_decimal(90.0));
+
+        assert_close(separation((zero, zero), (Angle::from_decimal(6.0), zero), Unit::Hours), 90.0 * 3600.0);
+        assert_close(separation((zero, zero), (Angle::from_decimal(12.0), zero), Unit::Hours), 180.0 * 3600.0);
+        assert_close(separation((zero, pole), (Angle::from_decimal(7.0), -pole), Unit::Hours), 180.0 * 3600.0);
+        assert_close(separation((Angle::from_decimal(350.0), zero), (Angle::from_decimal(10.0), zero), Unit::Degrees), 20.0 * 3600.0);
+        // tiny separations keep their precision
+        assert_close(separation((zero, zero), (zero, Angle::from_seconds(1.0)), Unit::Degrees), 1.0);
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{Serialize, Deserialize};
+
+use crate::data::MountStatus;
+
+/// Messages sent by the backend to the window
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BackendEvent {
+    /// Current state of the mount
+    Status(MountStatus),
+    /// The command with this sequence number was executed.
+    /// Acknowledgements are optional: a backend may never send them.
+    Accepted(u64),
+    /// The command with this sequence number could not be executed
+    Rejected(u64)
+}
@@ -0,0 +1,26 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Serialize, Deserialize};
+
+use crate::events::UserEvent;
+
+// shared by every producer of commands, so that sequence numbers never collide
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A `UserEvent` tagged with a sequence number, as sent to the backend
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Command {
+    /// strictly increasing, in the order the events were emitted
+    pub seq: u64,
+    pub event: UserEvent
+}
+
+impl Command {
+    /// Tags the event with the next sequence number
+    pub fn new(event: UserEvent) -> Command {
+        Command {
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+            event
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use orbtk::prelude::*;
+use orbtk::shell::KeyEvent;
+
+pub type KeyHandlerFn = dyn Fn(&mut StatesContext, &KeyEvent) -> bool + 'static;
+
+/// Handles both the key-down and the key-up events, told apart by `KeyEvent::state`
+pub struct KeyEventHandler {
+    pub handler: Rc<KeyHandlerFn>
+}
+
+impl From<KeyEventHandler> for Rc<dyn EventHandler> {
+    fn from(handler: KeyEventHandler) -> Self {
+        Rc::new(handler)
+    }
+}
+
+impl EventHandler for KeyEventHandler {
+    fn handle_event(&self, states: &mut StatesContext, event: &EventBox) -> bool {
+        if let Ok(event) = event.downcast_ref::<KeyDownEvent>() {
+            return (self.handler)(states, &event.event);
+        }
+        if let Ok(event) = event.downcast_ref::<KeyUpEvent>() {
+            return (self.handler)(states, &event.event);
+        }
+
+        false
+    }
+
+    fn handles_event(&self, event: &EventBox) -> bool {
+        event.is_type::<KeyDownEvent>() || event.is_type::<KeyUpEvent>()
+    }
+}
@@ -1,3 +1,9 @@
 mod user;
+mod command;
+mod backend;
+mod key;
 
 pub use self::user::*;
+pub use self::command::*;
+pub use self::backend::*;
+pub use self::key::*;
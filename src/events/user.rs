@@ -1,18 +1,47 @@
 pub use std::rc::Rc;
 
 use orbtk::prelude::*;
+use serde::{Serialize, Deserialize};
 
-use crate::data::{Angle, Direction};
+use crate::data::{Angle, Direction, SlewRate, TrackingMode, ParkPosition};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum UserEvent {
     Zero,
     Rotate(Angle, Angle),
-    BeginMove(Direction),
+    /// Tells the backend that the telescope points at this right ascension and declination,
+    /// e.g. after centering a known star
+    Sync(Angle, Angle),
+    /// Starts moving the axis of the direction. Both axes move independently,
+    /// so a diagonal move is a `BeginMove` on each of them.
+    BeginMove(Direction, SlewRate),
+    /// Stops the move of the axis in this direction
     EndMove(Direction),
+    /// Heartbeat sent while a move is held. Moves not kept alive are stopped by the backend.
+    KeepMoving,
+    /// Stops at once any slew, goto or manual move
+    Abort,
+    /// Starts tracking in the given mode, or stops tracking with None
+    Track(Option<TrackingMode>),
+    /// Slews to the position, stops tracking and locks the mount there
+    Park(ParkPosition),
+    /// Unlocks a parked mount and starts tracking again
+    Unpark,
+    /// Searches the reference position of the encoders
+    FindHome,
     Position(Angle, Angle)
 }
 
+impl UserEvent {
+    /// true if the event moves the mount, which a parked mount refuses
+    pub fn moves(&self) -> bool {
+        matches!(self,
+            UserEvent::Zero | UserEvent::Rotate(_, _) | UserEvent::BeginMove(_, _)
+            | UserEvent::Track(Some(_)) | UserEvent::FindHome
+        )
+    }
+}
+
 pub type UserHandlerFn = dyn Fn(&mut StatesContext, &UserEvent) -> bool + 'static;
 
 pub struct UserEventHandler {
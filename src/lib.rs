@@ -4,8 +4,14 @@ mod widgets;
 mod events;
 mod custom_app;
 
+pub mod wire;
+pub mod backends;
+pub mod stellarium;
+pub mod alignment;
+
 pub use main_app::make_window;
-pub use events::UserEvent;
+pub use events::{UserEvent, Command, BackendEvent};
+pub use data::{Angle, AngleKind, Sign, Unit, Direction, Axis, SlewRate, TrackingMode, SIDEREAL_RATE, ParkPosition, MountStatus, ParseAngleError, parse_sexagesimal, separation, horizontal_to_equatorial, equatorial_to_horizontal, greenwich_sidereal_time, local_sidereal_time};
 
 /*
 use std::sync::mpsc;
@@ -13,12 +19,17 @@ use std::thread;
 
 fn main() {
     let (sx, rx) = mpsc::channel();
+    let (status_sx, status_rx) = mpsc::channel();
     let handle = thread::spawn(|| {
-        make_window(sx);
+        make_window(sx, status_rx, alignment::SharedModel::default());
     });
 
+    status_sx.send(BackendEvent::Status(MountStatus::default())).unwrap();
+
     for _ in 0..5 {
-        println!("Received {:?}", rx.recv().unwrap());
+        let command: Command = rx.recv().unwrap();
+        println!("Received {:?}", command);
+        status_sx.send(BackendEvent::Accepted(command.seq)).unwrap();
     }
 
     handle.join().unwrap();
@@ -1,15 +1,30 @@
 use orbtk::prelude::*;
+use orbtk::shell::{ButtonState, Key};
 use crate::{
-    widgets::{PadView, RotationView, PositionView},
-    events::{UserEvent},
+    widgets::{AlignmentView, PadView, ParkView, RotationView, PositionView, StatusView, TrackingView},
+    events::{UserEvent, Command, BackendEvent, KeyEventHandler},
+    data::{Angle, MountStatus},
+    alignment::SharedModel,
     custom_app::CustomApplication
 };
 use orbtk::theming::config::ThemeConfig;
 use orbtk::theme::{COLORS_RON, DARK_THEME_RON, FONTS_RON};
+use std::collections::VecDeque;
 use std::sync::mpsc;
+use std::thread;
 
 static EXT: &str = include_str!("../res/theme.ron");
 
+static STATUS: &str = "status_view";
+static ROTATION: &str = "rotation_view";
+static PAD: &str = "pad_view";
+static TRACKING: &str = "tracking_view";
+static PARK: &str = "park_view";
+static ALIGNMENT: &str = "alignment_view";
+
+/// number of sent commands remembered while waiting for an acknowledgement
+const MAX_UNACKNOWLEDGED: usize = 32;
+
 fn theme() -> Theme {
     Theme::from_config(
         ThemeConfig::from(DARK_THEME_RON)
@@ -21,23 +36,173 @@ fn theme() -> Theme {
 
 #[derive(Default, AsAny)]
 pub struct MainViewState {
-    user_event: Option<UserEvent>
+    /// events waiting to be sent, in emission order
+    pending: VecDeque<Command>,
+    /// sent commands, kept to describe them when the backend acknowledges them
+    sent: VecDeque<Command>,
+
+    /// true if the moves held on the pad must be released, e.g. because the window lost the focus
+    release_pad: bool,
+
+    /// true if the backend reported the mount as parked
+    parked: bool,
+    /// last event not sent because the mount is parked
+    refused: Option<UserEvent>,
+
+    /// longitude and latitude of the site, once validated, not yet given to the RotationView
+    site: Option<(Angle, Angle)>,
+
+    status_view: Entity,
+    rotation_view: Entity,
+    pad_view: Entity,
+    tracking_view: Entity,
+    park_view: Entity,
+    alignment_view: Entity
 }
 
 impl MainViewState {
     fn register_event(&mut self, evt: UserEvent) {
-        self.user_event = Some(evt);
+        // a parked mount must be unparked before moving
+        if self.parked && evt.moves() {
+            self.refused = Some(evt);
+            return;
+        }
+        if let UserEvent::Position(longitude, latitude) = evt {
+            self.site = Some((longitude, latitude));
+        }
+        self.pending.push_back(Command::new(evt));
+    }
+
+    /// Sends an abort before the pending commands, which it makes obsolete
+    fn abort(&mut self) {
+        self.pending.clear();
+        self.pending.push_back(Command::new(UserEvent::Abort));
+        self.release_pad = true;
     }
+
+    fn send_pending(&mut self, registry: &mut Registry, ctx: &mut Context) {
+        let sender = registry.get::<mpsc::Sender<Command>>("sender");
+
+        while let Some(command) = self.pending.pop_front() {
+            if let Err(mpsc::SendError(command)) = sender.send(command) {
+                // the backend is gone: keep the command rather than losing it
+                self.pending.push_front(command);
+                break;
+            }
+
+            self.set_command_text(ctx, command, "envoyée");
+            self.sent.push_back(command);
+            if self.sent.len() > MAX_UNACKNOWLEDGED {
+                self.sent.pop_front();
+            }
+        }
+    }
+
+    fn acknowledge(&mut self, ctx: &mut Context, seq: u64, accepted: bool) {
+        if let Some(index) = self.sent.iter().position(|c| c.seq == seq) {
+            let command = self.sent.remove(index).unwrap();
+            self.set_command_text(ctx, command, if accepted { "acceptée" } else { "refusée" });
+        }
+    }
+
+    fn handle_backend_event(&mut self, ctx: &mut Context, event: BackendEvent) {
+        match event {
+            BackendEvent::Status(status) => {
+                ctx.get_widget(self.status_view).set::<MountStatus>("status", status);
+                ctx.get_widget(self.tracking_view).set::<MountStatus>("status", status);
+                ctx.get_widget(self.park_view).set::<MountStatus>("status", status);
+                ctx.get_widget(self.alignment_view).set::<MountStatus>("status", status);
+                self.parked = status.parked;
+            },
+            BackendEvent::Accepted(seq) => self.acknowledge(ctx, seq, true),
+            BackendEvent::Rejected(seq) => self.acknowledge(ctx, seq, false)
+        }
+    }
+
+    fn set_command_text(&self, ctx: &mut Context, command: Command, outcome: &str) {
+        // the heartbeats would hide the other commands
+        if command.event == UserEvent::KeepMoving {
+            return;
+        }
+        let text = format!("{} (n°{}) {}", describe(&command.event), command.seq, outcome);
+        ctx.get_widget(self.status_view).set::<String16>("command_text", String16::from(text));
+    }
+}
+
+/// Starts a thread handing the events of the backend to the view as messages, as soon as they arrive.
+/// It stops when the backend closes the channel.
+fn forward_backend_events(ctx: &mut Context, receiver: mpsc::Receiver<BackendEvent>) {
+    let adapter = ctx.message_adapter();
+    let entity = ctx.entity();
+
+    thread::spawn(move || {
+        for event in receiver {
+            adapter.send_message(event, entity);
+        }
+    });
 }
 
 impl State for MainViewState {
-    fn init(&mut self, _: &mut Registry, _: &mut Context) {
+    fn init(&mut self, registry: &mut Registry, ctx: &mut Context) {
+        self.status_view = ctx.entity_of_child(STATUS)
+            .expect("MainViewState.init(): the child status_view could not be found!");
+        self.rotation_view = ctx.entity_of_child(ROTATION)
+            .expect("MainViewState.init(): the child rotation_view could not be found!");
+        self.pad_view = ctx.entity_of_child(PAD)
+            .expect("MainViewState.init(): the child pad_view could not be found!");
+        self.tracking_view = ctx.entity_of_child(TRACKING)
+            .expect("MainViewState.init(): the child tracking_view could not be found!");
+        self.park_view = ctx.entity_of_child(PARK)
+            .expect("MainViewState.init(): the child park_view could not be found!");
+        self.alignment_view = ctx.entity_of_child(ALIGNMENT)
+            .expect("MainViewState.init(): the child alignment_view could not be found!");
+
+        // polled in `update`, the channel would only be read when a widget changes
+        if let Some(receiver) = registry.get_mut::<Option<mpsc::Receiver<BackendEvent>>>("receiver").take() {
+            forward_backend_events(ctx, receiver);
+        }
     }
 
-    fn update(&mut self, registry: &mut Registry, _: &mut Context) {
-        if let Some(e) = self.user_event {
-            registry.get::<mpsc::Sender<UserEvent>>("sender").send(e).unwrap();
+    fn messages(&mut self, mut messages: MessageReader, _: &mut Registry, ctx: &mut Context) {
+        for event in messages.read::<BackendEvent>() {
+            self.handle_backend_event(ctx, event);
+        }
+    }
+
+    fn update(&mut self, registry: &mut Registry, ctx: &mut Context) {
+        if self.release_pad {
+            self.release_pad = false;
+            ctx.get_widget(self.pad_view).set("release", true);
+        }
+
+        if let Some(site) = self.site.take() {
+            ctx.get_widget(self.rotation_view).set::<Option<(Angle, Angle)>>("site", Some(site));
+        }
+
+        if let Some(evt) = self.refused.take() {
+            let text = format!("{} refusée : télescope parqué", describe(&evt));
+            ctx.get_widget(self.status_view).set::<String16>("command_text", String16::from(text));
         }
+
+        self.send_pending(registry, ctx);
+    }
+}
+
+fn describe(event: &UserEvent) -> &'static str {
+    match event {
+        UserEvent::Zero => "Remise à zéro",
+        UserEvent::Rotate(_, _) => "Rotation",
+        UserEvent::Sync(_, _) => "Synchronisation",
+        UserEvent::BeginMove(_, _) => "Début de déplacement",
+        UserEvent::EndMove(_) => "Fin de déplacement",
+        UserEvent::KeepMoving => "Maintien du déplacement",
+        UserEvent::Abort => "Arrêt d'urgence",
+        UserEvent::Track(Some(_)) => "Suivi",
+        UserEvent::Track(None) => "Arrêt du suivi",
+        UserEvent::Park(_) => "Parcage",
+        UserEvent::Unpark => "Libération",
+        UserEvent::FindHome => "Recherche de l'origine",
+        UserEvent::Position(_, _) => "Position"
     }
 }
 
@@ -45,44 +210,127 @@ widget!(MainView<MainViewState> {});
 
 impl Template for MainView {
     fn template(self, id: Entity, ctx: &mut BuildContext) -> Self {
-        self.name("MainView").width(212).height(700).child(
-            Stack::new().spacing(10.0).child(
-                RotationView::new()
-                    .on_user_event(move |states, evt| {
-                        state(id, states).register_event(*evt);
-                        true
-                    })
-                    .build(ctx)
-            ).child(
-                PositionView::new()
-                    .on_user_event(move |states, evt| {
-                        state(id, states).register_event(*evt);
-                        true
-                    })
-                    .build(ctx)
-            ).child(
-                PadView::new()
-                    .on_user_event(move |states, evt| {
-                        state(id, states).register_event(*evt);
-                        true
-                    })
+        let controls = Stack::new().width(212).spacing(10.0).child(
+            RotationView::new()
+                .id(ROTATION)
+                .on_user_event(move |states, evt| {
+                    state(id, states).register_event(*evt);
+                    true
+                })
+                .build(ctx)
+        ).child(
+            PositionView::new()
+                .on_user_event(move |states, evt| {
+                    state(id, states).register_event(*evt);
+                    true
+                })
+                .build(ctx)
+        ).child(
+            PadView::new()
+                .id(PAD)
+                .on_user_event(move |states, evt| {
+                    state(id, states).register_event(*evt);
+                    true
+                })
+                .build(ctx)
+        )
+        .build(ctx);
+
+        self.name("MainView").height(700)
+            // Escape stops the mount, wherever the focus is
+            .insert_handler(KeyEventHandler {
+                handler: Rc::new(move |states, event| {
+                    if event.key == Key::Escape && event.state == ButtonState::Down {
+                        state(id, states).abort();
+                        return true;
+                    }
+                    false
+                })
+            })
+            .child(
+                Stack::new().orientation("horizontal").spacing(20.0)
+                    .child(controls)
+                    .child(
+                        Stack::new().spacing(10.0)
+                            .child(
+                                Button::new()
+                                    .style("stop_button")
+                                    .text("STOP (Échap)")
+                                    .on_click(move |states, _| {
+                                        state(id, states).abort();
+                                        true
+                                    })
+                                    .build(ctx)
+                            )
+                            .child(
+                                StatusView::new()
+                                    .id(STATUS)
+                                    .precision(1)
+                                    .build(ctx)
+                            )
+                            .child(
+                                TrackingView::new()
+                                    .id(TRACKING)
+                                    .on_user_event(move |states, evt| {
+                                        state(id, states).register_event(*evt);
+                                        true
+                                    })
+                                    .build(ctx)
+                            )
+                            .build(ctx)
+                    )
+                    .child(
+                        Stack::new().width(300).spacing(10.0)
+                            .child(
+                                ParkView::new()
+                                    .id(PARK)
+                                    .on_user_event(move |states, evt| {
+                                        state(id, states).register_event(*evt);
+                                        true
+                                    })
+                                    .build(ctx)
+                            )
+                            .child(
+                                AlignmentView::new()
+                                    .id(ALIGNMENT)
+                                    .on_user_event(move |states, evt| {
+                                        state(id, states).register_event(*evt);
+                                        true
+                                    })
+                                    .build(ctx)
+                            )
+                            .build(ctx)
+                    )
                     .build(ctx)
             )
-            .build(ctx)
-        )
     }
 }
 
 
-pub fn make_window(sx: mpsc::Sender<UserEvent>) {
+/// Opens the window. User events are sent through `sx` as numbered commands,
+/// while the statuses and acknowledgements of the backend are read from `rx`.
+/// The alignment done in the window is stored in `alignment`, for the `Aligned` backend to apply it.
+pub fn make_window(sx: mpsc::Sender<Command>, rx: mpsc::Receiver<BackendEvent>, alignment: SharedModel) {
     CustomApplication::new()
         .theme(theme())
-        .window(sx, |ctx| {
+        .window(sx, rx, alignment, |ctx| {
+            let main_view = MainView::new().build(ctx);
+
             Window::new()
-                .title("OrbTk - minimal example")
+                .title("Beer - pilotage du télescope")
                 .position((100.0, 100.0))
-                .size(420.0, 730.0)
-                .child(MainView::new().build(ctx))
+                .size(1040.0, 730.0)
+                .insert_handler(WindowEventHandler {
+                    handler: Rc::new(move |states, event| {
+                        if let WindowEvent::ActiveChanged(false) = event {
+                            // the buttons and keys held will not be released in the window anymore
+                            state(main_view, states).release_pad = true;
+                            return true;
+                        }
+                        false
+                    })
+                })
+                .child(main_view)
                 .build(ctx)
         })
         .run();
@@ -0,0 +1,248 @@
+//! Server speaking the binary telescope protocol of Stellarium ("Telescope Control" plugin,
+//! "External software or a remote computer" connection).
+//!
+//! A goto requested from the planetarium is sent to the backend as a `UserEvent::Rotate`,
+//! unless the mount is parked, and the position reported by the backend is streamed back to the planetarium.
+//!
+//! The server shares the commands channel of the window, and gets its own copy of the statuses:
+//!
+//! ```ignore
+//! let (sender, commands) = mpsc::channel();
+//! let (events, receiver) = mpsc::channel();
+//! let (observer, statuses) = mpsc::channel();
+//!
+//! let listener = TcpListener::bind(("0.0.0.0", STELLARIUM_PORT))?;
+//! let planetarium = sender.clone();
+//! thread::spawn(move || serve(listener, planetarium, statuses));
+//! thread::spawn(move || {
+//!     let mut backend = Simulator::new(SimulatorConfig::default());
+//!     backends::run_with_observers(&mut backend, commands, events, vec![observer], Duration::from_secs(1))
+//! });
+//! make_window(sender, receiver, SharedModel::default());
+//! ```
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    data::{Angle, AngleKind, MountStatus},
+    events::{UserEvent, Command}
+};
+
+
+/// Port used by convention by the Stellarium plugin
+pub const STELLARIUM_PORT: u16 = 10001;
+
+/// Period at which the position is sent to the planetarium
+const POSITION_PERIOD: Duration = Duration::from_millis(500);
+
+// type of both the goto and the current position messages
+const MESSAGE_TYPE: u16 = 0;
+const GOTO_LENGTH: usize = 20;
+const POSITION_LENGTH: usize = 24;
+
+// full circle in seconds of time, and quarter circle in seconds of arc
+const DAY_SECONDS: f64 = 24.0 * 3600.0;
+const RIGHT_ANGLE_SECONDS: f64 = 90.0 * 3600.0;
+
+
+/// Serves the planetarium clients connecting to `listener`, one at a time.
+/// Gotos are sent through `commands`, and the positions to stream are read from `statuses`.
+/// Returns once a goto cannot be sent anymore, i.e. when the receiver of `commands` is gone.
+pub fn serve(
+    listener: TcpListener,
+    commands: mpsc::Sender<Command>,
+    statuses: mpsc::Receiver<MountStatus>) -> io::Result<()> {
+    // the statuses keep coming while no client is connected: only the last one is kept
+    let status = Arc::new(Mutex::new(MountStatus::default()));
+    let latest = status.clone();
+    thread::spawn(move || {
+        for received in statuses {
+            *latest.lock().unwrap() = received;
+        }
+    });
+
+    for stream in listener.incoming() {
+        // the errors of a client only end its connection
+        if let Ok(false) = serve_client(stream?, &commands, &status) {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Serves a client until it disconnects. Returns false if the window was closed meanwhile.
+fn serve_client(
+    mut stream: TcpStream,
+    commands: &mpsc::Sender<Command>,
+    status: &Mutex<MountStatus>) -> io::Result<bool> {
+    stream.set_read_timeout(Some(POSITION_PERIOD))?;
+
+    let mut received = vec![];
+    let mut last_position: Option<Instant> = None;
+
+    loop {
+        let mut buffer = [0u8; 64];
+        match stream.read(&mut buffer) {
+            Ok(0) => return Ok(true),
+            Ok(n) => received.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {},
+            Err(e) => return Err(e)
+        }
+
+        while let Some((right_asc, decl)) = read_goto(&mut received) {
+            // as in the window, a parked mount must be unparked before moving
+            if status.lock().unwrap().parked {
+                continue;
+            }
+            if commands.send(Command::new(UserEvent::Rotate(right_asc, decl))).is_err() {
+                return Ok(false);
+            }
+        }
+
+        let due = match last_position {
+            Some(instant) => instant.elapsed() >= POSITION_PERIOD,
+            None => true
+        };
+        if due {
+            last_position = Some(Instant::now());
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_micros() as i64)
+                .unwrap_or(0);
+            stream.write_all(&position_message(&status.lock().unwrap(), time))?;
+        }
+    }
+}
+
+/// Extracts the first message from `received`, returning the target if it is a goto
+fn read_goto(received: &mut Vec<u8>) -> Option<(Angle, Angle)> {
+    loop {
+        if received.len() < 4 {
+            return None;
+        }
+
+        let length = u16::from_le_bytes([received[0], received[1]]) as usize;
+        if length < 4 {
+            // not a valid message: drop everything and wait for the stream to resynchronize
+            received.clear();
+            return None;
+        }
+        if received.len() < length {
+            return None;
+        }
+
+        let message: Vec<u8> = received.drain(..length).collect();
+        let kind = u16::from_le_bytes([message[2], message[3]]);
+        if kind != MESSAGE_TYPE || length != GOTO_LENGTH {
+            continue;
+        }
+
+        // message[4..12] is the time of the client, which is not needed
+        let right_asc = u32::from_le_bytes([message[12], message[13], message[14], message[15]]);
+        let decl = i32::from_le_bytes([message[16], message[17], message[18], message[19]]);
+
+        return Some((
+            // 0x1_0000_0000 is 24h
+            Angle::from_seconds(right_asc as f64 * DAY_SECONDS / (1u64 << 32) as f64),
+            // 0x4000_0000 is 90°
+            Angle::from_seconds(decl as f64 * RIGHT_ANGLE_SECONDS / (1u64 << 30) as f64)
+        ));
+    }
+}
+
+/// Message of the current position, at `time` in microseconds since the epoch
+fn position_message(status: &MountStatus, time: i64) -> Vec<u8> {
+    let right_asc = (AngleKind::RightAscension.normalize(status.right_asc).to_seconds() / DAY_SECONDS * (1u64 << 32) as f64).round() as u64 as u32;
+    let decl = (status.decl.to_seconds() / RIGHT_ANGLE_SECONDS * (1u64 << 30) as f64).round() as i32;
+
+    let mut message = Vec::with_capacity(POSITION_LENGTH);
+    message.extend_from_slice(&(POSITION_LENGTH as u16).to_le_bytes());
+    message.extend_from_slice(&MESSAGE_TYPE.to_le_bytes());
+    message.extend_from_slice(&time.to_le_bytes());
+    message.extend_from_slice(&right_asc.to_le_bytes());
+    message.extend_from_slice(&decl.to_le_bytes());
+    // status: 0 means ok
+    message.extend_from_slice(&0i32.to_le_bytes());
+    message
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Sign;
+
+    /// Goto message, with the coordinates in the units of the protocol
+    fn goto(right_asc: u32, decl: i32) -> Vec<u8> {
+        let mut message = vec![20, 0, 0, 0];
+        message.extend_from_slice(&123456789i64.to_le_bytes());
+        message.extend_from_slice(&right_asc.to_le_bytes());
+        message.extend_from_slice(&decl.to_le_bytes());
+        message
+    }
+
+    #[test]
+    fn gotos() {
+        // 6h, -45°
+        let mut received = vec![20, 0, 0, 0, 0x15, 0xcd, 0x5b, 0x07, 0, 0, 0, 0, 0, 0, 0, 0x40, 0, 0, 0, 0xe0];
+        assert_eq!(received, goto(0x4000_0000, -0x2000_0000));
+        assert_eq!(read_goto(&mut received), Some((Angle(Sign::Positive, 6, 0, 0.0), Angle(Sign::Negative, 45, 0, 0.0))));
+        assert!(received.is_empty());
+
+        // 18h, 1'30" south (0x4000_0000 * 90 / 324000): the sign is kept for the angles under 1°
+        let mut received = goto(0xc000_0000, -298_262);
+        let (right_asc, decl) = read_goto(&mut received).unwrap();
+        assert_eq!(right_asc, Angle(Sign::Positive, 18, 0, 0.0));
+        assert_eq!(decl.0, Sign::Negative);
+        assert!((decl.to_seconds() + 90.0).abs() < 0.01, "{:?}", decl);
+    }
+
+    #[test]
+    fn split_goto() {
+        let message = goto(0x8000_0000, 0x1000_0000);
+        let mut received = message[..3].to_vec();
+        assert_eq!(read_goto(&mut received), None);
+        received.extend_from_slice(&message[3..15]);
+        assert_eq!(read_goto(&mut received), None);
+        received.extend_from_slice(&message[15..]);
+        received.extend_from_slice(&message[..10]);
+        assert_eq!(read_goto(&mut received), Some((Angle(Sign::Positive, 12, 0, 0.0), Angle(Sign::Positive, 22, 30, 0.0))));
+        assert_eq!(received, message[..10]);
+    }
+
+    #[test]
+    fn other_messages() {
+        // a message of another type, then a goto
+        let mut received = vec![6, 0, 1, 0, 0xff, 0xff];
+        received.extend(goto(0, 0));
+        assert_eq!(read_goto(&mut received), Some((Angle::default(), Angle::default())));
+
+        // an invalid length is dropped with what follows
+        let mut received = vec![2, 0, 0, 0];
+        received.extend(goto(0, 0));
+        assert_eq!(read_goto(&mut received), None);
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn position() {
+        let status = MountStatus {
+            right_asc: Angle(Sign::Negative, 6, 0, 0.0),
+            decl: Angle(Sign::Negative, 45, 0, 0.0),
+            ..Default::default()
+        };
+        assert_eq!(position_message(&status, 1_600_000_000_000_000), vec![
+            24, 0, 0, 0,
+            0x00, 0x00, 0xa4, 0x07, 0x31, 0xaf, 0x05, 0x00,
+            // -6h is 18h
+            0x00, 0x00, 0x00, 0xc0,
+            0x00, 0x00, 0x00, 0xe0,
+            0, 0, 0, 0
+        ]);
+    }
+}
@@ -0,0 +1,193 @@
+use orbtk::prelude::*;
+
+use crate::{
+    alignment::{AlignmentModel, AlignmentStar, SharedModel, BRIGHT_STARS, separation_error},
+    events::{UserEvent, UserEventHandler},
+    data::MountStatus
+};
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Action {
+    /// starts an alignment on this number of stars, forgetting the current model
+    Start(usize),
+    Previous,
+    Next,
+    /// slews to the selected star
+    Goto,
+    /// records the selected star as centered
+    Confirm,
+    Cancel
+}
+
+#[derive(Default, AsAny)]
+pub struct AlignmentViewState {
+    /// index of the selected star in `BRIGHT_STARS`
+    star: usize,
+    /// number of stars of the alignment in progress, 0 if none is
+    wanted: usize,
+    /// stars centered so far
+    centered: Vec<(usize, AlignmentStar)>,
+    action: Option<Action>,
+    /// shown instead of the progress, until the next action
+    message: Option<String>
+}
+
+impl AlignmentViewState {
+    fn action(&mut self, action: Action) {
+        self.action = Some(action);
+    }
+
+    fn confirm(&mut self, ctx: &mut Context) {
+        if self.wanted == 0 {
+            self.message = Some("Commencer par choisir le nombre d'étoiles".to_string());
+            return;
+        }
+        if self.centered.iter().any(|(star, _)| *star == self.star) {
+            self.message = Some("Étoile déjà utilisée : en choisir une autre".to_string());
+            return;
+        }
+
+        let status = *ctx.widget().get::<MountStatus>("status");
+        let star = &BRIGHT_STARS[self.star];
+        self.centered.push((self.star, AlignmentStar {
+            sky: (star.right_asc, star.decl),
+            mount: (status.right_asc, status.decl)
+        }));
+        if self.centered.len() < self.wanted {
+            return;
+        }
+
+        let stars: Vec<AlignmentStar> = self.centered.iter().map(|(_, star)| *star).collect();
+        let model = AlignmentModel::from_stars(&stars);
+        self.message = Some(match model {
+            Some(_) => format!("Écart entre les étoiles : {:.0}", separation_error(&stars)),
+            None => "Étoiles trop proches : recommencer".to_string()
+        });
+        ctx.widget().set::<Option<AlignmentModel>>("model", model);
+        self.wanted = 0;
+        self.centered.clear();
+    }
+}
+
+impl State for AlignmentViewState {
+    fn update(&mut self, registry: &mut Registry, ctx: &mut Context) {
+        if let Some(action) = self.action.take() {
+            self.message = None;
+            match action {
+                Action::Start(count) => {
+                    // neither the gotos to the stars nor their positions must be corrected by the previous model
+                    ctx.widget().set::<Option<AlignmentModel>>("model", None);
+                    self.wanted = count;
+                    self.centered.clear();
+                },
+                Action::Previous => self.star = (self.star + BRIGHT_STARS.len() - 1) % BRIGHT_STARS.len(),
+                Action::Next => self.star = (self.star + 1) % BRIGHT_STARS.len(),
+                Action::Goto => {
+                    let star = &BRIGHT_STARS[self.star];
+                    ctx.push_event(UserEvent::Rotate(star.right_asc, star.decl));
+                },
+                Action::Confirm => self.confirm(ctx),
+                Action::Cancel => {
+                    self.wanted = 0;
+                    self.centered.clear();
+                }
+            }
+        }
+
+        let model = *ctx.widget().get::<Option<AlignmentModel>>("model");
+        // applied by the backend to every goto and sync
+        registry.get::<SharedModel>("alignment").set(model);
+
+        let step = match (&self.message, model) {
+            (Some(message), _) => message.clone(),
+            _ if self.wanted > 0 => format!(
+                "Étoile {}/{} : pointer, centrer avec le pavé, puis valider",
+                self.centered.len() + 1, self.wanted
+            ),
+            (None, Some(model)) => format!("Aligné sur {} étoiles", model.stars),
+            (None, None) => "Non aligné".to_string()
+        };
+        ctx.widget().set::<String16>("step_text", String16::from(step));
+        ctx.widget().set::<String16>("star_text", String16::from(BRIGHT_STARS[self.star].name));
+    }
+}
+
+
+widget!(AlignmentView<AlignmentViewState> {
+    /// last status reported by the backend, set by the MainView
+    status: MountStatus,
+
+    /// correction of the pointing, None until an alignment is done
+    model: Option<AlignmentModel>,
+
+    // automatically set
+    step_text: String16,
+    star_text: String16
+});
+
+
+impl AlignmentView {
+    pub fn on_user_event<H: Fn(&mut StatesContext, &UserEvent) -> bool + 'static>(
+        self,
+        handler: H,
+    ) -> Self {
+        self.insert_handler(UserEventHandler {
+            handler: Rc::new(handler),
+        })
+    }
+}
+
+
+fn generate_button(ctx: &mut BuildContext, id: Entity, text: &str, action: Action) -> Entity {
+    Button::new()
+        .text(text)
+        .on_click(move |states, _| {
+            state(id, states).action(action);
+            true
+        })
+        .margin((0, 0, 4, 0))
+        .build(ctx)
+}
+
+impl Template for AlignmentView {
+    fn template(self, id: Entity, ctx: &mut BuildContext) -> Self {
+        self.name("AlignmentView")
+            .step_text("Non aligné")
+            .star_text(BRIGHT_STARS[0].name)
+            .child(Stack::new().spacing(10.0).child(
+                TextBlock::new()
+                    .text("Alignement")
+                    .font_size(25)
+                    .build(ctx)
+            )
+            .child(TextBlock::new().text(("step_text", id)).build(ctx))
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_button(ctx, id, "2 étoiles", Action::Start(2)))
+                    .child(generate_button(ctx, id, "3 étoiles", Action::Start(3)))
+                    .child(generate_button(ctx, id, "Annuler", Action::Cancel))
+                    .build(ctx)
+            )
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_button(ctx, id, "<", Action::Previous))
+                    .child(TextBlock::new().text(("star_text", id)).v_align("center").margin((0, 0, 4, 0)).build(ctx))
+                    .child(generate_button(ctx, id, ">", Action::Next))
+                    .build(ctx)
+            )
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_button(ctx, id, "Pointer", Action::Goto))
+                    .child(generate_button(ctx, id, "Valider", Action::Confirm))
+                    .build(ctx)
+            )
+            .build(ctx)
+        )
+    }
+}
+
+// helper to request AlignmentViewState
+fn state<'a>(id: Entity, states: &'a mut StatesContext) -> &'a mut AlignmentViewState {
+    states.get_mut(id)
+}
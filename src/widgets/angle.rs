@@ -1,6 +1,6 @@
 use orbtk::prelude::*;
 use crate::{
-    data::Angle,
+    data::{Angle, AngleKind, Sign, parse_sexagesimal},
     widgets::NumericTextBox
 };
 
@@ -10,26 +10,63 @@ use crate::{
 static HIGH_INPUT: &str = "high_input";
 static MID_INPUT: &str = "mid_input";
 static LOW_INPUT: &str = "low_input";
+static SIGN_BUTTON: &str = "sign_button";
 
 
 #[derive(Default, AsAny)]
 pub struct AngleViewState {
     angle: Angle,
+    sign: Sign,
+    toggle_sign: bool,
 
     high_input: Entity,
     mid_input: Entity,
-    low_input: Entity
+    low_input: Entity,
+    sign_button: Entity
 }
 
-fn first_symbol(first_angle: bool) -> &'static str {
-    if first_angle {"°"} else {"h"}
+/// Labels of the sign button used when none are given
+fn default_labels(kind: AngleKind) -> (&'static str, &'static str) {
+    match kind {
+        AngleKind::Latitude => ("N", "S"),
+        AngleKind::Longitude => ("E", "O"),
+        _ => ("+", "-")
+    }
+}
+
+/// true if the text of an input holds more than its own field: units, sign or decimals
+/// (the latter being allowed in the `fractional` seconds input)
+fn is_coordinate(text: &str, fractional: bool) -> bool {
+    text.trim().chars().any(|c| !(c.is_ascii_digit() || (fractional && (c == '.' || c == ','))))
+}
+
+/// true if the text is a single decimal number, e.g. "-83.633"
+fn is_decimal(text: &str) -> bool {
+    text.trim().trim_start_matches(&['+', '-', '−'][..]).chars().all(|c| c.is_ascii_digit() || c == '.')
 }
 
-fn first_max_value(first_angle: bool) -> usize {
-    if first_angle { 360 } else { 24 }
+/// Exclusive upper bound of the degrees (or hours) input
+fn first_max_value(kind: AngleKind) -> usize {
+    // the limit itself is included in the range of signed angles (e.g. 90°)
+    kind.limit() as usize + kind.signed() as usize
 }
 
 impl AngleViewState { 
+    fn toggle_sign(&mut self) {
+        self.toggle_sign = true;
+    }
+
+    fn update_sign_text(&self, ctx: &mut Context) {
+        let (positive, negative) = default_labels(*ctx.widget().get::<AngleKind>("kind"));
+        let (key, default) = match self.sign {
+            Sign::Positive => ("positive_label", positive),
+            Sign::Negative => ("negative_label", negative)
+        };
+        let label = ctx.widget().get::<String16>(key).clone().as_string();
+        let text = if label.is_empty() { default.to_string() } else { label };
+        ctx.widget().set::<String16>("sign_text", String16::from(text));
+    }
+
     fn handle_carries(&mut self, ctx: &mut Context) {
         if *ctx.get_widget(self.low_input).get::<bool>("underflow") {
             ctx.get_widget(self.low_input).set("underflow", false);
@@ -49,6 +86,59 @@ impl AngleViewState {
         }
     }
 
+    /// Carry out of the degrees (or hours) input: 1 if it went over its maximum, -1 if it went below 0
+    fn high_carry(&mut self, ctx: &mut Context) -> i64 {
+        let mut carry = 0;
+        if *ctx.get_widget(self.high_input).get::<bool>("underflow") {
+            ctx.get_widget(self.high_input).set("underflow", false);
+            carry -= 1;
+        }
+        if *ctx.get_widget(self.high_input).get::<bool>("overflow") {
+            ctx.get_widget(self.high_input).set("overflow", false);
+            carry += 1;
+        }
+        carry
+    }
+
+    /// Looks for a whole coordinate (e.g. "05h 34m 31.94s", "83.633°", or "83.633" in the first input)
+    /// typed or pasted into one of the inputs.
+    /// Returns the angle to show: the coordinate, or the current angle if the coordinate is out of the range.
+    fn pasted_angle(&self, ctx: &mut Context) -> Option<Angle> {
+        let kind = *ctx.widget().get::<AngleKind>("kind");
+
+        for (key, fractional) in [("value1", false), ("value2", false), ("value3", true)] {
+            let text = ctx.widget().get::<String16>(key).clone().as_string();
+            if !is_coordinate(&text, fractional) {
+                continue;
+            }
+
+            let text = text.replace(',', ".");
+            // a decimal number in the minutes or the seconds is only a fraction of them
+            if key != "value1" && is_decimal(&text) {
+                continue;
+            }
+            if let Ok((angle, unit)) = parse_sexagesimal(&text) {
+                // without unit, in the unit of the input: the decimal right ascensions
+                // given in degrees by the catalogs need an explicit "°"
+                let angle = angle.convert(unit.unwrap_or(kind.unit()), kind.unit());
+                // most likely a mistake: rejected rather than brought back within the range
+                return Some(if kind.contains(angle) { angle } else { self.angle });
+            }
+        }
+
+        None
+    }
+
+    /// Shows the angle in the inputs
+    fn display(&mut self, angle: Angle, ctx: &mut Context) {
+        self.sign = angle.0;
+        self.update_sign_text(ctx);
+        angle_view(ctx.widget()).set_value1(angle.1.to_string());
+        angle_view(ctx.widget()).set_value2(angle.2.to_string());
+        let precision = *ctx.widget().get::<usize>("precision");
+        angle_view(ctx.widget()).set_value3(format!("{:.*}", precision, angle.3));
+    }
+
     fn check_validity(&mut self, ctx: &mut Context) -> bool {
         let valid = *ctx.get_widget(self.low_input).get::<bool>("valid") 
             && *ctx.get_widget(self.mid_input).get::<bool>("valid")
@@ -64,15 +154,18 @@ impl AngleViewState {
 impl State for AngleViewState {
     fn init(&mut self, _: &mut Registry, ctx: &mut Context) {
         // initialize the inputs to zero
-        self.angle = Angle(0, 0, 0);
+        self.angle = Angle::default();
+        self.sign = Sign::Positive;
+        self.toggle_sign = false;
+        self.update_sign_text(ctx);
         angle_view(ctx.widget()).set_value1("0");
         angle_view(ctx.widget()).set_value2("0");
         angle_view(ctx.widget()).set_value3("0");
 
-        // set variables according to the kind of angle (hour or degree)
-        let first_angle = *ctx.widget().get::<bool>("first_angle");
-        angle_view(ctx.widget()).set_value1_suffix(first_symbol(first_angle));
-        angle_view(ctx.widget()).set_value1_max(first_max_value(first_angle));
+        // set variables according to the kind of angle
+        let kind = *ctx.widget().get::<AngleKind>("kind");
+        angle_view(ctx.widget()).set_value1_suffix(kind.suffix());
+        angle_view(ctx.widget()).set_value1_max(first_max_value(kind));
 
         // fetch the inputs
         self.high_input = ctx.entity_of_child(HIGH_INPUT)
@@ -81,26 +174,66 @@ impl State for AngleViewState {
             .expect("AngleViewState.init(): the child mid input could not be found!");
         self.low_input = ctx.entity_of_child(LOW_INPUT)
             .expect("AngleViewState.init(): the child low input could not be found!");
+        self.sign_button = ctx.entity_of_child(SIGN_BUTTON)
+            .expect("AngleViewState.init(): the child sign button could not be found!");
+
+        // angles which are never negative (e.g. right ascensions) have no sign to choose
+        if !kind.signed() {
+            ctx.get_widget(self.sign_button).set("visibility", Visibility::Collapsed);
+        }
     } 
 
     fn update(&mut self, _: &mut Registry, ctx: &mut Context) {
+        if self.toggle_sign {
+            self.toggle_sign = false;
+            self.sign = self.sign.opposite();
+            self.update_sign_text(ctx);
+        }
+
+        // the inputs are updated after this view: they will read the new texts, and the
+        // values they hold now are outdated
+        if let Some(angle) = self.pasted_angle(ctx) {
+            self.display(angle, ctx);
+            return;
+        }
+
         self.handle_carries(ctx);
+        let carry = self.high_carry(ctx);
         let valid = self.check_validity(ctx);
 
         if valid {
-            self.angle = Angle(
-                *ctx.get_widget(self.high_input).get::<i32>("value") as i16,
-                *ctx.get_widget(self.mid_input).get::<i32>("value") as u8,
-                *ctx.get_widget(self.low_input).get::<i32>("value") as u8
-            );
+            let kind = *ctx.widget().get::<AngleKind>("kind");
+            let high = *ctx.get_widget(self.high_input).get::<f64>("value");
+            let mid = *ctx.get_widget(self.mid_input).get::<f64>("value");
+            let low = *ctx.get_widget(self.low_input).get::<f64>("value");
+
+            let angle = Angle(self.sign, high as u16, mid as u8, low);
+            self.angle = if carry == 0 && kind.contains(angle) {
+                angle
+            } else {
+                // the degrees input wrapped around on its own: undo it, then apply the rules of the kind
+                let magnitude = (high + (carry * first_max_value(kind) as i64) as f64) * 3600.0 + mid * 60.0 + low;
+                let seconds = if self.sign == Sign::Negative { -magnitude } else { magnitude };
+                let angle = kind.normalize(Angle::from_seconds(seconds));
+                self.display(angle, ctx);
+                angle
+            };
             ctx.widget().set::<Angle>("angle", self.angle);
         }
     }
 }
 
 widget!(AngleView<AngleViewState> {
-    /// If true, then value1 is an angle, else it is an hour
-    first_angle: bool,
+    /// What the angle measures, which gives its unit and its range
+    kind: AngleKind,
+
+    /// Text of the sign button for positive and negative angles, which defaults
+    /// to "N" and "S" for latitudes, "E" and "O" for longitudes, else "+" and "-"
+    positive_label: String16,
+    negative_label: String16,
+
+    /// Number of decimals of the seconds
+    precision: usize,
 
     // automatically set
     value1_suffix: String16,
@@ -108,6 +241,7 @@ widget!(AngleView<AngleViewState> {
     value1: String16,
     value2: String16,
     value3: String16,
+    sign_text: String16,
     valid: bool,
     angle: Angle
 });
@@ -118,6 +252,17 @@ impl Template for AngleView {
         self.name("Angleview").child(
             Stack::new().orientation("horizontal")
             .child(
+                Button::new()
+                    .id(SIGN_BUTTON)
+                    .text(("sign_text", id))
+                    .on_click(move |states, _| {
+                        state(id, states).toggle_sign();
+                        true
+                    })
+                    .margin((5, 0, 5, 0))
+                    .max_width(36)
+                    .build(ctx)
+            ).child(
                 NumericTextBox::new()
                     .id(HIGH_INPUT)
                     .text(("value1", id))
@@ -141,6 +286,7 @@ impl Template for AngleView {
                 NumericTextBox::new()
                     .id(LOW_INPUT)
                     .text(("value3", id))
+                    .precision(("precision", id))
                     .suffix("\"")
                     .neg_value(false)
                     .max_value(60)
@@ -152,3 +298,8 @@ impl Template for AngleView {
         )
     }
 }
+
+// helper to request AngleViewState
+fn state<'a>(id: Entity, states: &'a mut StatesContext) -> &'a mut AngleViewState {
+    states.get_mut(id)
+}
@@ -1,11 +1,19 @@
 mod numeric_text_box;
 mod angle;
+mod alignment;
 mod pad;
+mod park;
 mod rotation;
 mod position;
+mod status;
+mod tracking;
 
 pub use self::numeric_text_box::*;
 pub use self::angle::*;
+pub use self::alignment::*;
 pub use self::pad::*;
+pub use self::park::*;
 pub use self::rotation::*;
 pub use self::position::*;
+pub use self::status::*;
+pub use self::tracking::*;
@@ -1,8 +1,17 @@
+use std::env;
+
 use orbtk::prelude::*;
 
 
 static ID_INPUT: &str = "numeric_text_box_input";
 
+// languages writing "3,5" rather than "3.5", for a guess of the separator from the locale. Only the most common ones
+// are listed and the region is ignored, so e.g. de_CH gets a comma and en_ZA a point: setting the decimal_separator
+// of the widget is the only reliable way
+static COMMA_LANGUAGES: &[&str] = &[
+    "fr", "de", "es", "it", "pt", "nl", "ru", "pl", "cs", "sk", "sv", "da", "nb", "nn", "fi", "tr", "el", "uk", "ro", "hu"
+];
+
 
 enum Action {
     Inc, 
@@ -14,13 +23,25 @@ enum Action {
 pub struct NumericTextBoxState {
     /// true if negative values are allowed
     neg_value: bool,
+
+    /// true if the bounds are inclusive and clamp the value, instead of wrapping it around
+    float_mode: bool,
     
-    min_value: i32,
+    min_value: f64,
     /// max
-    max_value: i32,
+    max_value: f64,
+
+    /// difference made by the buttons and the carries
+    step: f64,
 
-    /// current value, clamped between 0 and max_value if neg_value, else -max_value and max_value
-    value: i32,
+    /// current value, clamped between 0 and max_value if neg_value, else -max_value and max_value,
+    /// or between min and max in float mode
+    value: f64,
+
+    /// number of decimals
+    precision: usize,
+
+    decimal_separator: char,
 
     action: Option<Action>,
 
@@ -34,65 +55,136 @@ impl NumericTextBoxState {
         self.action = Some(action);
     }
 
-    fn min_value(&self) -> i32 {
+    fn min_value(&self) -> f64 {
         if self.neg_value {
-            -(self.max_value as i32) + 1
+            -self.max_value + 1.0
         } else {
-            0
+            0.0
         }
     }
 
-    fn clamp_value(&mut self) {
-        if self.value < self.min_value {
-            self.value = self.min_value;
-        } else if self.value >= self.max_value {
-            self.value = self.max_value - 1;
+    /// smallest difference between two values
+    fn resolution(&self) -> f64 {
+        10f64.powi(-(self.precision as i32))
+    }
+
+    fn round(&self, value: f64) -> f64 {
+        let scale = 10f64.powi(self.precision as i32);
+        (value * scale).round() / scale
+    }
+
+    /// greatest value allowed
+    fn upper_bound(&self) -> f64 {
+        if self.float_mode {
+            self.max_value
+        } else {
+            self.max_value - self.resolution()
         }
     }
 
-    fn add_step_value(&mut self, new_value: i32, ctx: &mut Context) {
+    /// Returns true if the value had to be changed
+    fn clamp_value(&mut self) -> bool {
+        // unlike f64::clamp, does not panic if there is no value allowed (e.g. max_value of 0)
+        let clamped = self.value.min(self.upper_bound()).max(self.min_value);
+        let changed = clamped != self.value;
+        self.value = clamped;
+        changed
+    }
+
+    fn add_step_value(&mut self, new_value: f64, ctx: &mut Context) {
+        let new_value = self.round(new_value);
+        if self.float_mode {
+            // the value stops at the bounds
+            if new_value < self.min_value {
+                ctx.widget().set("underflow", true);
+            } else if new_value > self.max_value {
+                ctx.widget().set("overflow", true);
+            }
+            self.value = new_value.min(self.max_value).max(self.min_value);
+            return;
+        }
+
+        // the values wrap around, keeping their decimals
+        let span = self.max_value - self.min_value;
         if new_value < self.min_value {
             ctx.widget().set("underflow", true);
-            self.value = self.max_value - 1;
+            self.value = self.round(new_value + span);
         } else if new_value >= self.max_value {
             ctx.widget().set("overflow", true);
-            self.value = self.min_value;
+            self.value = self.round(new_value - span);
         } else {
             self.value = new_value;
         }
     }
 
-    fn check_validity(&mut self, ctx: &mut Context) {
-        let text = ctx.widget().get::<String16>("text").clone().as_string();
-        self.value = match text.parse::<i32>() {
-            Ok(v) => {self.valid = true; v}
-            Err(_) => {self.valid = false; self.value}
+    fn format_value(&self) -> String {
+        let text = format!("{:.*}", self.precision, self.value);
+        text.replace('.', &self.decimal_separator.to_string())
+    }
+
+    /// Returns true if the value had to be changed to be within the bounds and the precision
+    fn check_validity(&mut self, ctx: &mut Context) -> bool {
+        // both the separator of the locale and the point are accepted
+        let text = ctx.widget().get::<String16>("text").clone().as_string()
+            .replace(self.decimal_separator, ".");
+        let mut changed = false;
+        self.value = match text.trim().parse::<f64>() {
+            Ok(v) if v.is_finite() => {
+                self.valid = true;
+                changed = self.round(v) != v;
+                self.round(v)
+            },
+            _ => {self.valid = false; self.value}
         };
-        self.clamp_value();
+        changed |= self.clamp_value();
         ctx.widget().set::<bool>("valid", self.valid);
+        changed
     }
 }
 
 impl State for NumericTextBoxState {
     fn init(&mut self, _: &mut Registry, ctx: &mut Context) {
-        self.value = 0;
-        self.max_value = *ctx.widget().get::<usize>("max_value") as i32;
-        self.neg_value = *ctx.widget().get::<bool>("neg_value"); 
-        self.min_value = self.min_value();
+        self.value = 0.0;
+        self.float_mode = *ctx.widget().get::<bool>("float_mode");
+        if self.float_mode {
+            self.min_value = *ctx.widget().get::<f64>("min");
+            self.max_value = *ctx.widget().get::<f64>("max");
+            // bounds given the wrong way round
+            if self.min_value > self.max_value {
+                std::mem::swap(&mut self.min_value, &mut self.max_value);
+            }
+            // not set: the buttons would do nothing
+            self.step = match *ctx.widget().get::<f64>("step") {
+                step if step > 0.0 => step,
+                _ => 1.0
+            };
+            self.value = self.min_value.max(0.0).min(self.max_value);
+        } else {
+            self.max_value = *ctx.widget().get::<usize>("max_value") as f64;
+            self.neg_value = *ctx.widget().get::<bool>("neg_value"); 
+            self.min_value = self.min_value();
+            self.step = 1.0;
+        }
+
+        let separator = ctx.widget().get::<String16>("decimal_separator").clone().as_string();
+        self.decimal_separator = separator.chars().next().unwrap_or_else(locale_decimal_separator);
         self.input_entity = ctx.entity_of_child(ID_INPUT).expect("NumericBoxTextState.init(): the child input could not be found!");
 
         self.valid = false;
     }
 
     fn update(&mut self, _: &mut Registry, ctx: &mut Context) {
-        self.check_validity(ctx);
+        self.precision = *ctx.widget().get::<usize>("precision");
+        let mut changed = self.check_validity(ctx);
 
         if self.valid {
             if *ctx.widget().get::<bool>("should_inc") {
-                self.add_step_value(self.value + 1, ctx);
+                self.add_step_value(self.value + self.step, ctx);
+                changed = true;
             }
             if *ctx.widget().get::<bool>("should_dec") {
-                self.add_step_value(self.value - 1, ctx);
+                self.add_step_value(self.value - self.step, ctx);
+                changed = true;
             }
 
             ctx.widget().set("should_inc", false);
@@ -100,14 +192,19 @@ impl State for NumericTextBoxState {
 
             if let Some(action) = &self.action {
                 match action {
-                    Action::Inc => self.add_step_value(self.value + 1, ctx),
-                    Action::Dec => self.add_step_value(self.value - 1, ctx), 
+                    Action::Inc => self.add_step_value(self.value + self.step, ctx),
+                    Action::Dec => self.add_step_value(self.value - self.step, ctx), 
                 }
+                changed = true;
             }
             self.action = None;
         
-            ctx.widget().set::<String16>("text", String16::from(self.value.to_string()));
-            ctx.widget().set::<i32>("value", self.value);
+            // rewriting the text while it is typed would get in the way (e.g. "12." would become "12.0")
+            if changed || !*ctx.widget().get::<bool>("focused") {
+                let text = self.format_value();
+                ctx.widget().set::<String16>("text", String16::from(text));
+            }
+            ctx.widget().set::<f64>("value", self.value);
 
             ctx.widget().set::<Brush>("background", "#3b434a".into());
         } else {
@@ -123,7 +220,25 @@ widget!(NumericTextBox<NumericTextBoxState> {
     /// max possible value (exclusive upper bound)
     max_value: usize,
 
-    value: i32,
+    /// if true, the value goes from min to max (both included) by steps of step,
+    /// and stops at the bounds instead of wrapping around.
+    /// neg_value and max_value are then ignored.
+    float_mode: bool,
+
+    /// float mode only: bounds of the value, swapped if min is greater than max
+    min: f64,
+    max: f64,
+
+    /// float mode only: difference made by the buttons and the carries, 1 if not set
+    step: f64,
+
+    /// number of decimals of the value, 0 for integers
+    precision: usize,
+
+    /// separator of the decimals. If empty, guessed from the language of the locale, which may be wrong
+    decimal_separator: String16,
+
+    value: f64,
 
     /// text of the TextBox
     text: String16,
@@ -217,4 +332,13 @@ fn state<'a>(id: Entity, states: &'a mut StatesContext) -> &'a mut NumericTextBo
     states.get_mut(id)
 }
 
+/// Decimal separator guessed from the language of the locale of the environment
+fn locale_decimal_separator() -> char {
+    let locale = ["LC_ALL", "LC_NUMERIC", "LANG"].iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let language = locale.split(&['_', '.', '@'][..]).next().unwrap_or("");
 
+    if COMMA_LANGUAGES.contains(&language) { ',' } else { '.' }
+}
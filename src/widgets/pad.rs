@@ -1,52 +1,266 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
 use orbtk::prelude::*;
+use orbtk::shell::{ButtonState, Key, KeyEvent};
+
+use crate::data::{Axis, Direction, SlewRate};
+use crate::events::{UserEvent, UserEventHandler, KeyEventHandler};
+use crate::widgets::NumericTextBox;
+
+static ID_MULTIPLE: &str = "pad_view_multiple";
+
+/// Period of the heartbeats sent while moving, well below `backends::WATCHDOG_TIMEOUT`
+const HEARTBEAT_PERIOD: Duration = Duration::from_millis(250);
+
+/// Sent to the pad by its ticker thread, while a move is in progress
+struct Heartbeat;
 
-use crate::data::Direction;
-use crate::events::{UserEvent, UserEventHandler};
+/// What holds a direction
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Input {
+    Mouse,
+    Keyboard
+}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Action {
-    BeginMove(Direction),
-    EndMove(Direction),
-    Zero
+    /// a direction is pressed
+    BeginMove(Direction, Input),
+    /// a direction is released
+    EndMove(Direction, Input),
+    /// every direction held with the input is released
+    Release(Input),
+    Zero,
+    /// selects the rate of the moves
+    Rate(SlewRate),
+    /// selects the multiple of the sidereal rate typed in the input
+    SiderealMultiple
+}
+
+/// Keys driving the pad. Letters match whether they are typed in upper or lower case.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyBindings {
+    pub up: Vec<Key>,
+    pub down: Vec<Key>,
+    pub left: Vec<Key>,
+    pub right: Vec<Key>,
+    pub zero: Vec<Key>
+}
+
+impl Default for KeyBindings {
+    /// Arrows or WASD to move, 0 to go back to zero
+    fn default() -> Self {
+        KeyBindings {
+            up: vec![Key::Up, Key::W(false)],
+            down: vec![Key::Down, Key::S(false)],
+            left: vec![Key::Left, Key::A(false)],
+            right: vec![Key::Right, Key::D(false)],
+            zero: vec![Key::Zero, Key::Numpad0]
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Action triggered by pressing or releasing `key`, None if it is not bound
+    fn action(&self, key: Key, pressed: bool) -> Option<Action> {
+        let bound = |keys: &[Key]| keys.iter().any(|&k| same_key(k, key));
+
+        if bound(&self.zero) {
+            return if pressed { Some(Action::Zero) } else { None };
+        }
+
+        let dir = [
+            (&self.up, Direction::Up),
+            (&self.down, Direction::Down),
+            (&self.left, Direction::Left),
+            (&self.right, Direction::Right)
+        ].iter().find(|(keys, _)| bound(keys)).map(|&(_, d)| d)?;
+
+        Some(if pressed { Action::BeginMove(dir, Input::Keyboard) } else { Action::EndMove(dir, Input::Keyboard) })
+    }
 }
 
+/// true if both keys are the same, ignoring the case of the letters
+fn same_key(first: Key, second: Key) -> bool {
+    let text = |key: Key| <&str>::from(key).to_lowercase();
+    first == second || (!text(first).is_empty() && text(first) == text(second))
+}
+
+
 #[derive(Default, AsAny)]
 pub struct PadViewState {
-    // directional pad
-    pressed_btn: Option<Direction>,
+    /// directions held with the buttons or the keys, from the oldest to the latest
+    held: Vec<(Direction, Input)>,
+
+    /// direction in which each axis is moving
+    moving: HashMap<Axis, Direction>,
+
+    /// cleared to stop the thread sending the heartbeats
+    ticking: Option<Arc<AtomicBool>>,
 
-    action: Vec<Action>
+    action: Vec<Action>,
+
+    /// keys pressed (true) or released (false) since the last update
+    keys: Vec<(Key, bool)>
 }
 
 impl PadViewState {
     fn action(&mut self, action: Action) {
         self.action.push(action);
     }
+
+    fn key(&mut self, event: &KeyEvent) {
+        self.keys.push((event.key, event.state == ButtonState::Down));
+    }
+
+    /// Turns the keys into actions, while no other widget (e.g. a text box) has the focus
+    fn handle_keys(&mut self, ctx: &mut Context) {
+        let entity = ctx.entity();
+        let active = match ctx.window().get::<FocusState>("focus_state").focused_entity() {
+            Some(focused) => *focused == entity,
+            None => true
+        };
+        let bindings = ctx.widget().clone::<KeyBindings>("bindings");
+
+        for (key, pressed) in self.keys.drain(..) {
+            match bindings.action(key, pressed) {
+                Some(action @ Action::BeginMove(..)) if active => self.action.push(action),
+                // a move is always ended, even if the focus changed meanwhile
+                Some(action @ Action::EndMove(..)) => self.action.push(action),
+                Some(Action::Zero) if active => self.action.push(Action::Zero),
+                _ => {}
+            }
+        }
+    }
+
+    /// Starts and stops the moves of each axis, which follows the latest direction held on it
+    fn update_moves(&mut self, ctx: &mut Context) {
+        for &axis in &[Axis::RightAscension, Axis::Declination] {
+            let wanted = self.held.iter().rev().map(|&(d, _)| d).find(|d| d.axis() == axis);
+            let current = self.moving.get(&axis).copied();
+            if wanted == current {
+                continue;
+            }
+
+            if let Some(d) = current {
+                ctx.push_event(UserEvent::EndMove(d));
+                self.moving.remove(&axis);
+            }
+            if let Some(d) = wanted {
+                let rate = *ctx.widget().get::<SlewRate>("rate");
+                ctx.push_event(UserEvent::BeginMove(d, rate));
+                self.moving.insert(axis, d);
+            }
+        }
+
+        if self.moving.is_empty() {
+            if let Some(ticking) = self.ticking.take() {
+                ticking.store(false, Ordering::Relaxed);
+            }
+        } else if self.ticking.is_none() {
+            self.ticking = Some(start_ticker(ctx));
+        }
+    }
+}
+
+/// Starts a thread asking the pad to send a heartbeat every `HEARTBEAT_PERIOD`, until the returned flag is cleared.
+/// The heartbeats are sent by the UI thread, so that they stop if it is stuck.
+fn start_ticker(ctx: &mut Context) -> Arc<AtomicBool> {
+    let ticking = Arc::new(AtomicBool::new(true));
+    let flag = ticking.clone();
+    let adapter = ctx.message_adapter();
+    let entity = ctx.entity();
+
+    thread::spawn(move || {
+        loop {
+            thread::sleep(HEARTBEAT_PERIOD);
+            if !flag.load(Ordering::Relaxed) {
+                break;
+            }
+            adapter.send_message(Heartbeat, entity);
+        }
+    });
+
+    ticking
 }
 
 impl State for PadViewState {
-    fn init(&mut self, _: &mut Registry, _: &mut Context) {
+    fn init(&mut self, _: &mut Registry, ctx: &mut Context) {
         self.action = vec![];
+        self.keys = vec![];
+
+        let rate = *ctx.widget().get::<SlewRate>("rate");
+        set_rate(ctx, rate);
+    }
+
+    fn messages(&mut self, mut messages: MessageReader, _: &mut Registry, ctx: &mut Context) {
+        if messages.read::<Heartbeat>().count() > 0 && !self.moving.is_empty() {
+            ctx.push_event(UserEvent::KeepMoving);
+        }
     }
 
     fn update(&mut self, _: &mut Registry, ctx: &mut Context) {
+        self.handle_keys(ctx);
+
+        // e.g. the window lost the focus, and will not receive the release of the buttons or of the keys
+        if *ctx.widget().get::<bool>("release") {
+            self.held.clear();
+            ctx.widget().set("release", false);
+        }
+
         for a in self.action.drain(..) {
             match a {
-                Action::BeginMove(d) => {
-                    ctx.push_event(UserEvent::BeginMove(d));
-                    self.pressed_btn = Some(d);
-                },
-                Action::EndMove(d) => {
-                    ctx.push_event(UserEvent::EndMove(d));
-                    self.pressed_btn = None;
+                // the keyboard repeats the key-down events while a key is held
+                Action::BeginMove(d, input) => if !self.held.contains(&(d, input)) {
+                    self.held.push((d, input));
                 },
+                Action::EndMove(d, input) => self.held.retain(|&held| held != (d, input)),
+                Action::Release(input) => self.held.retain(|&(_, held)| held != input),
                 Action::Zero => ctx.push_event(UserEvent::Zero), 
+                Action::Rate(rate) => set_rate(ctx, rate),
+                Action::SiderealMultiple => {
+                    let multiple = *ctx.child(ID_MULTIPLE).get::<f64>("value");
+                    set_rate(ctx, SlewRate::Sidereal(multiple));
+                }
             }
         }
+
+        self.update_moves(ctx);
     }
 }
 
-widget!(PadView<PadViewState> {});
+fn set_rate(ctx: &mut Context, rate: SlewRate) {
+    ctx.widget().set::<SlewRate>("rate", rate);
+    ctx.widget().set::<String16>("rate_text", String16::from(format!("Vitesse : {}", rate_label(rate))));
+}
+
+fn rate_label(rate: SlewRate) -> String {
+    match rate {
+        SlewRate::Guide => "guidage".to_string(),
+        SlewRate::Centering => "centrage".to_string(),
+        SlewRate::Find => "recherche".to_string(),
+        SlewRate::Max => "maximale".to_string(),
+        SlewRate::Sidereal(multiple) => format!("{}× sidérale", multiple)
+    }
+}
+
+widget!(PadView<PadViewState>: MouseHandler {
+    /// keys driving the pad, arrows or WASD and 0 by default
+    bindings: KeyBindings,
+
+    /// set to true to stop every move, as if all the buttons and keys were released
+    release: bool,
+
+    /// rate of the moves, centering by default
+    rate: SlewRate,
+
+    // automatically set
+    rate_text: String16
+});
 
 impl PadView {
     pub fn on_user_event<H: Fn(&mut StatesContext, &UserEvent) -> bool + 'static>(
@@ -59,60 +273,116 @@ impl PadView {
     }
 }
 
+/// Generate a button of the pad, moving in `dirs` (both axes for the corners),
+/// or going back to zero if there is no direction.
+/// The moves end when the mouse is released anywhere, which `PadView` handles.
 fn generate_pad_button(
     ctx: &mut BuildContext, 
     id: Entity,
-    dir: Option<Direction>,
+    dirs: &[Direction],
     column: usize,
     row: usize) -> Entity {
+    let pressed = dirs.to_vec();
     let mut button = Button::new()
         .style("pad_button")
         .on_mouse_down(move |states, _| -> bool {
-            state(id, states).action(
-                match dir {
-                    Some(d) => Action::BeginMove(d),
-                    None => Action::Zero
-                });
+            if pressed.is_empty() {
+                state(id, states).action(Action::Zero);
+            }
+            for &d in &pressed {
+                state(id, states).action(Action::BeginMove(d, Input::Mouse));
+            }
             true
         })
-        .on_mouse_up(move |states, _| -> bool {
-            if let Some(current) = state(id, states).pressed_btn {
-                if let Some(d) = dir {
-                    if d != current { 
-                        return false; 
-                    }
-                    state(id, states).action(Action::EndMove(d));  
-                }
-            }
-            false
-        }) 
         .attach(Grid::column(column))
         .attach(Grid::row(row));
     
-    if let Some(d) = dir {
-        button = button.icon(image_path(d));
-    } else {
+    if dirs.is_empty() {
         button = button.text("0")
+    } else {
+        button = button.icon(image_path(dirs));
     }
 
     button.build(ctx)
 }
 
+/// Generate a button selecting a rate
+fn generate_rate_button(ctx: &mut BuildContext, id: Entity, text: &str, action: Action) -> Entity {
+    Button::new()
+        .text(text)
+        .on_click(move |states, _| {
+            state(id, states).action(action);
+            true
+        })
+        .margin((0, 0, 4, 0))
+        .build(ctx)
+}
+
 impl Template for PadView {
     fn template(self, id: Entity, ctx: &mut BuildContext) -> Self {
         let btn_size = 72;
 
-        self.name("PadView").child(
-            Grid::new() 
-                .rows(Rows::create().push(btn_size).push(btn_size).push(btn_size))
-                .columns(Columns::create().push(btn_size).push(btn_size).push(btn_size))
-                .child(generate_pad_button(ctx, id, None, 1, 1))
-                .child(generate_pad_button(ctx, id, Some(Direction::Up), 1, 0))
-                .child(generate_pad_button(ctx, id, Some(Direction::Down), 1, 2))
-                .child(generate_pad_button(ctx, id, Some(Direction::Left), 0, 1))
-                .child(generate_pad_button(ctx, id, Some(Direction::Right), 2, 1))
-                .build(ctx)
-        )
+        self.name("PadView")
+            .release(false)
+            // also released out of the buttons, e.g. after dragging the pointer off them
+            .on_global_mouse_up(move |states, _| {
+                state(id, states).action(Action::Release(Input::Mouse));
+            })
+            .insert_handler(KeyEventHandler {
+                handler: Rc::new(move |states, event| {
+                    state(id, states).key(event);
+                    false
+                })
+            })
+            .child(
+                Stack::new().spacing(8.0)
+                    .child(
+                        Grid::new() 
+                            .rows(Rows::create().push(btn_size).push(btn_size).push(btn_size))
+                            .columns(Columns::create().push(btn_size).push(btn_size).push(btn_size))
+                            .child(generate_pad_button(ctx, id, &[], 1, 1))
+                            .child(generate_pad_button(ctx, id, &[Direction::Up], 1, 0))
+                            .child(generate_pad_button(ctx, id, &[Direction::Down], 1, 2))
+                            .child(generate_pad_button(ctx, id, &[Direction::Left], 0, 1))
+                            .child(generate_pad_button(ctx, id, &[Direction::Right], 2, 1))
+                            .child(generate_pad_button(ctx, id, &[Direction::Up, Direction::Left], 0, 0))
+                            .child(generate_pad_button(ctx, id, &[Direction::Up, Direction::Right], 2, 0))
+                            .child(generate_pad_button(ctx, id, &[Direction::Down, Direction::Left], 0, 2))
+                            .child(generate_pad_button(ctx, id, &[Direction::Down, Direction::Right], 2, 2))
+                            .build(ctx)
+                    )
+                    .child(TextBlock::new().text(("rate_text", id)).build(ctx))
+                    .child(
+                        Stack::new().orientation("horizontal")
+                            .child(generate_rate_button(ctx, id, "Guidage", Action::Rate(SlewRate::Guide)))
+                            .child(generate_rate_button(ctx, id, "Centrage", Action::Rate(SlewRate::Centering)))
+                            .build(ctx)
+                    )
+                    .child(
+                        Stack::new().orientation("horizontal")
+                            .child(generate_rate_button(ctx, id, "Recherche", Action::Rate(SlewRate::Find)))
+                            .child(generate_rate_button(ctx, id, "Max", Action::Rate(SlewRate::Max)))
+                            .build(ctx)
+                    )
+                    .child(
+                        Stack::new().orientation("horizontal")
+                            .child(
+                                NumericTextBox::new()
+                                    .id(ID_MULTIPLE)
+                                    .float_mode(true)
+                                    .min(0.5)
+                                    .max(1000.0)
+                                    .step(1.0)
+                                    .precision(1)
+                                    .suffix("×")
+                                    .margin((0, 0, 4, 0))
+                                    .build(ctx)
+                            )
+                            .child(generate_rate_button(ctx, id, "Sidérale", Action::SiderealMultiple))
+                            .build(ctx)
+                    )
+                    .build(ctx)
+            )
     }
 }
 
@@ -121,12 +391,16 @@ fn state<'a>(id: Entity, states: &'a mut StatesContext) -> &'a mut PadViewState
     states.get_mut(id)
 }
 
-fn image_path(dir: Direction) -> &'static str {
-    match dir {
-        Direction::Up => material_icons_font::MD_ARROW_UPWARD,
-        Direction::Down => material_icons_font::MD_ARROW_DOWNWARD,
-        Direction::Right => material_icons_font::MD_ARROW_FORWARD,
-        Direction::Left => material_icons_font::MD_ARROW_BACK
+fn image_path(dirs: &[Direction]) -> &'static str {
+    match dirs {
+        [Direction::Up] => material_icons_font::MD_ARROW_UPWARD,
+        [Direction::Down] => material_icons_font::MD_ARROW_DOWNWARD,
+        [Direction::Right] => material_icons_font::MD_ARROW_FORWARD,
+        [Direction::Left] => material_icons_font::MD_ARROW_BACK,
+        [Direction::Up, Direction::Left] => material_icons_font::MD_NORTH_WEST,
+        [Direction::Up, Direction::Right] => material_icons_font::MD_NORTH_EAST,
+        [Direction::Down, Direction::Left] => material_icons_font::MD_SOUTH_WEST,
+        [Direction::Down, Direction::Right] => material_icons_font::MD_SOUTH_EAST,
+        _ => ""
     }
 }
-
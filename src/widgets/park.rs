@@ -0,0 +1,262 @@
+use orbtk::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    widgets::AngleView,
+    events::{UserEvent, UserEventHandler},
+    data::{Angle, AngleKind, ParkPosition, MountStatus}
+};
+
+
+static ALTITUDE: &str = "park_altitude_input";
+static AZIMUTH: &str = "park_azimuth_input";
+
+/// Key of the saved positions in the settings
+static SETTINGS_KEY: &str = "park_positions";
+
+
+/// Park positions kept between the sessions
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct SavedPositions {
+    positions: Vec<(String, ParkPosition)>,
+    selected: usize
+}
+
+impl Default for SavedPositions {
+    fn default() -> Self {
+        SavedPositions {
+            positions: vec![("Zénith".to_string(), ParkPosition { altitude: Angle::from_decimal(90.0), azimuth: Angle::default() })],
+            selected: 0
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Action {
+    Park,
+    Unpark,
+    FindHome,
+    Previous,
+    Next,
+    /// saves the position typed in the inputs
+    SaveInput,
+    /// saves the position where the mount points
+    SaveCurrent,
+    Delete
+}
+
+#[derive(Default, AsAny)]
+pub struct ParkViewState {
+    saved: SavedPositions,
+    action: Option<Action>,
+    /// why the positions could not be saved, shown until the next save succeeds
+    save_error: Option<String>,
+
+    altitude_input: Entity,
+    azimuth_input: Entity
+}
+
+impl ParkViewState {
+    fn action(&mut self, action: Action) {
+        self.action = Some(action);
+    }
+
+    fn selected(&self) -> Option<&(String, ParkPosition)> {
+        self.saved.positions.get(self.saved.selected)
+    }
+
+    /// Adds the position, or replaces the one of the same name, and selects it
+    fn save(&mut self, name: String, position: ParkPosition) {
+        match self.saved.positions.iter().position(|(n, _)| *n == name) {
+            Some(index) => {
+                self.saved.positions[index].1 = position;
+                self.saved.selected = index;
+            },
+            None => {
+                self.saved.positions.push((name, position));
+                self.saved.selected = self.saved.positions.len() - 1;
+            }
+        }
+    }
+
+    fn store(&mut self, registry: &mut Registry) {
+        self.save_error = registry.get::<Settings>("settings").save(SETTINGS_KEY, &self.saved).err();
+    }
+}
+
+impl State for ParkViewState {
+    fn init(&mut self, registry: &mut Registry, ctx: &mut Context) {
+        self.altitude_input = ctx.entity_of_child(ALTITUDE)
+            .expect("ParkViewState.init(): the child park_altitude_input could not be found!");
+        self.azimuth_input = ctx.entity_of_child(AZIMUTH)
+            .expect("ParkViewState.init(): the child park_azimuth_input could not be found!");
+
+        // nothing saved yet on the first launch
+        self.saved = registry.get::<Settings>("settings").load::<SavedPositions>(SETTINGS_KEY).unwrap_or_default();
+        self.action = None;
+    }
+
+    fn update(&mut self, registry: &mut Registry, ctx: &mut Context) {
+        let status = *ctx.widget().get::<MountStatus>("status");
+        let count = self.saved.positions.len();
+
+        match self.action.take() {
+            Some(Action::Park) => {
+                if let Some((_, position)) = self.selected() {
+                    ctx.push_event(UserEvent::Park(*position));
+                }
+            },
+            Some(Action::Unpark) => ctx.push_event(UserEvent::Unpark),
+            Some(Action::FindHome) => ctx.push_event(UserEvent::FindHome),
+            Some(Action::Previous) if count > 0 => {
+                self.saved.selected = (self.saved.selected + count - 1) % count;
+                self.store(registry);
+            },
+            Some(Action::Next) if count > 0 => {
+                self.saved.selected = (self.saved.selected + 1) % count;
+                self.store(registry);
+            },
+            Some(Action::SaveInput) => {
+                let position = ParkPosition {
+                    altitude: *ctx.get_widget(self.altitude_input).get::<Angle>("angle"),
+                    azimuth: *ctx.get_widget(self.azimuth_input).get::<Angle>("angle")
+                };
+                self.save(position_name(ctx, count), position);
+                self.store(registry);
+            },
+            Some(Action::SaveCurrent) => {
+                let position = ParkPosition { altitude: status.altitude, azimuth: status.azimuth };
+                self.save(position_name(ctx, count), position);
+                self.store(registry);
+            },
+            Some(Action::Delete) if count > 0 => {
+                self.saved.positions.remove(self.saved.selected);
+                self.saved.selected = self.saved.selected.min(self.saved.positions.len().saturating_sub(1));
+                self.store(registry);
+            },
+            _ => {}
+        }
+
+        let state_text = if status.parked { "État : parqué" } else { "État : libre" };
+        ctx.widget().set::<String16>("state_text", String16::from(match &self.save_error {
+            Some(e) => format!("{} (positions non enregistrées : {})", state_text, e),
+            None => state_text.to_string()
+        }));
+        ctx.widget().set::<String16>("selected_text", String16::from(match self.selected() {
+            Some((name, position)) => format!("{} (alt. {}, az. {})", name, position.altitude, position.azimuth),
+            None => "Aucune position enregistrée".to_string()
+        }));
+    }
+}
+
+/// Name typed by the user, or a numbered one if none was
+fn position_name(ctx: &mut Context, count: usize) -> String {
+    let name = ctx.widget().get::<String16>("position_name").clone().as_string();
+    match name.trim() {
+        "" => format!("Position {}", count + 1),
+        name => name.to_string()
+    }
+}
+
+
+widget!(ParkView<ParkViewState> {
+    /// last status reported by the backend, set by the MainView
+    status: MountStatus,
+
+    /// name under which the next position is saved
+    position_name: String16,
+
+    // automatically set
+    state_text: String16,
+    selected_text: String16
+});
+
+
+impl ParkView {
+    pub fn on_user_event<H: Fn(&mut StatesContext, &UserEvent) -> bool + 'static>(
+        self,
+        handler: H,
+    ) -> Self {
+        self.insert_handler(UserEventHandler {
+            handler: Rc::new(handler),
+        })
+    }
+}
+
+
+fn generate_button(ctx: &mut BuildContext, id: Entity, text: &str, action: Action) -> Entity {
+    Button::new()
+        .text(text)
+        .on_click(move |states, _| {
+            state(id, states).action(action);
+            true
+        })
+        .margin((0, 0, 4, 0))
+        .build(ctx)
+}
+
+fn generate_angle_line(ctx: &mut BuildContext, label: &str, input: &str, kind: AngleKind) -> Entity {
+    Stack::new().orientation("horizontal")
+        .child(
+            TextBlock::new()
+                .text(label)
+                .build(ctx)
+        ).child(
+            AngleView::new()
+                .id(input)
+                .kind(kind)
+                .precision(0)
+                .build(ctx)
+        ).build(ctx)
+}
+
+impl Template for ParkView {
+    fn template(self, id: Entity, ctx: &mut BuildContext) -> Self {
+        self.name("ParkView")
+            .state_text("État : libre")
+            .child(Stack::new().spacing(10.0).child(
+                TextBlock::new()
+                    .text("Parcage")
+                    .font_size(25)
+                    .build(ctx)
+            )
+            .child(TextBlock::new().text(("state_text", id)).build(ctx))
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_button(ctx, id, "Parquer", Action::Park))
+                    .child(generate_button(ctx, id, "Libérer", Action::Unpark))
+                    .child(generate_button(ctx, id, "Origine", Action::FindHome))
+                    .build(ctx)
+            )
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_button(ctx, id, "<", Action::Previous))
+                    .child(TextBlock::new().text(("selected_text", id)).v_align("center").margin((0, 0, 4, 0)).build(ctx))
+                    .child(generate_button(ctx, id, ">", Action::Next))
+                    .build(ctx)
+            )
+            .child(generate_button(ctx, id, "Supprimer", Action::Delete))
+            .child(generate_angle_line(ctx, "Altitude : ", ALTITUDE, AngleKind::Altitude))
+            .child(generate_angle_line(ctx, "Azimut : ", AZIMUTH, AngleKind::Azimuth))
+            .child(
+                TextBox::new()
+                    .water_mark("Nom de la position")
+                    .text(("position_name", id))
+                    .build(ctx)
+            )
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_button(ctx, id, "Enregistrer", Action::SaveInput))
+                    .child(generate_button(ctx, id, "Position actuelle", Action::SaveCurrent))
+                    .build(ctx)
+            )
+            .build(ctx)
+        )
+    }
+}
+
+// helper to request ParkViewState
+fn state<'a>(id: Entity, states: &'a mut StatesContext) -> &'a mut ParkViewState {
+    states.get_mut(id)
+}
@@ -3,7 +3,7 @@ use orbtk::prelude::*;
 use crate::{
     widgets::{AngleView},
     events::{UserEvent, UserEventHandler},
-    data::{Angle}
+    data::{Angle, AngleKind}
 };
 
 
@@ -104,7 +104,8 @@ impl Template for PositionView {
                     .child(
                         AngleView::new()
                             .id(LONGITUDE)
-                            .first_angle(true)
+                            .kind(AngleKind::Longitude)
+                            .precision(1)
                             .on_changed_filter(vec!["valid"])
                             .on_changed(move |states, _, _| {
                                 state(id, states).check_validity();
@@ -121,7 +122,8 @@ impl Template for PositionView {
                     ).child(
                         AngleView::new()
                             .id(LATITUDE)
-                            .first_angle(true)
+                            .kind(AngleKind::Latitude)
+                            .precision(1)
                             .on_changed_filter(vec!["valid"])
                             .on_changed(move |states, _, _| {
                                 state(id, states).check_validity();
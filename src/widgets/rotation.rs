@@ -1,9 +1,14 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
 use orbtk::prelude::*;
 
 use crate::{
     widgets::{AngleView},
     events::{UserEvent, UserEventHandler},
-    data::{Angle}
+    data::{Angle, AngleKind, Unit, equatorial_to_horizontal, greenwich_sidereal_time, local_sidereal_time}
 };
 
 
@@ -11,6 +16,13 @@ static RIGHT_ASC: &str = "right_asc_input";
 static DECL: &str = "decl_input";
 
 
+/// Period at which the sidereal time and the horizontal coordinates are refreshed
+const SKY_PERIOD: Duration = Duration::from_secs(1);
+
+/// Sent to the view by its clock thread
+struct Tick;
+
+
 static BTN_TEXT_VALID: &str = "Tourner le téléscope";
 static BTN_TEXT_INVALID: &str = "Angles de rotation invalides";
 
@@ -21,7 +33,11 @@ pub struct RotationViewState {
     decl_input: Entity,
 
     should_check_validity: bool,
-    button_pressed: bool
+    button_pressed: bool,
+    sync_pressed: bool,
+
+    /// cleared to stop the clock thread
+    ticking: Option<Arc<AtomicBool>>
 }
 
 impl RotationViewState {
@@ -32,6 +48,66 @@ impl RotationViewState {
     fn click(&mut self) {
         self.button_pressed = true;
     }
+
+    fn click_sync(&mut self) {
+        self.sync_pressed = true;
+    }
+}
+
+/// Shows the sidereal times, then the hour angle, altitude and azimuth of the typed target
+fn update_sky(ctx: &mut Context, right_asc: Angle, decl: Angle) {
+    let site = *ctx.widget().get::<Option<(Angle, Angle)>>("site");
+    let (longitude, latitude) = match site {
+        Some(site) => site,
+        None => {
+            ctx.widget().set::<String16>("sidereal_text", String16::from("Valider la position du site pour situer la cible"));
+            ctx.widget().set::<String16>("hour_angle_text", String16::default());
+            ctx.widget().set::<String16>("horizontal_text", String16::default());
+            return;
+        }
+    };
+
+    let now = SystemTime::now();
+    let local = local_sidereal_time(now, longitude);
+    ctx.widget().set::<String16>("sidereal_text", String16::from(format!(
+        "TS Greenwich : {}, local : {}",
+        greenwich_sidereal_time(now).to_sexagesimal(Unit::Hours, 0),
+        local.to_sexagesimal(Unit::Hours, 0)
+    )));
+
+    // between -12h (east) and 12h (west)
+    let hour_angle = (local.to_decimal() - right_asc.to_decimal() + 12.0).rem_euclid(24.0) - 12.0;
+    let hour_angle = Angle::from_decimal(hour_angle);
+    ctx.widget().set::<String16>("hour_angle_text", String16::from(
+        format!("Angle horaire : {}", hour_angle.to_sexagesimal(Unit::Hours, 0))
+    ));
+
+    let (altitude, azimuth) = equatorial_to_horizontal(hour_angle, decl, latitude);
+    let visibility = if altitude.is_negative() { " (sous l'horizon)" } else { "" };
+    ctx.widget().set::<String16>("horizontal_text", String16::from(
+        format!("Altitude : {}, azimut : {}{}", altitude, azimuth, visibility)
+    ));
+}
+
+/// Starts a thread asking the view to refresh the coordinates depending on the time, every `SKY_PERIOD`,
+/// until the returned flag is cleared
+fn start_clock(ctx: &mut Context) -> Arc<AtomicBool> {
+    let ticking = Arc::new(AtomicBool::new(true));
+    let flag = ticking.clone();
+    let adapter = ctx.message_adapter();
+    let entity = ctx.entity();
+
+    thread::spawn(move || {
+        loop {
+            thread::sleep(SKY_PERIOD);
+            if !flag.load(Ordering::Relaxed) {
+                break;
+            }
+            adapter.send_message(Tick, entity);
+        }
+    });
+
+    ticking
 }
 
 impl State for RotationViewState {
@@ -43,6 +119,23 @@ impl State for RotationViewState {
 
         self.should_check_validity = true;
         self.button_pressed = false;
+        self.sync_pressed = false;
+
+        self.ticking = Some(start_clock(ctx));
+    }
+
+    fn cleanup(&mut self, _: &mut Registry, _: &mut Context) {
+        if let Some(ticking) = self.ticking.take() {
+            ticking.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn messages(&mut self, mut messages: MessageReader, _: &mut Registry, ctx: &mut Context) {
+        if messages.read::<Tick>().count() > 0 {
+            let ra = *ctx.get_widget(self.right_asc_input).get::<Angle>("angle");
+            let de = *ctx.get_widget(self.decl_input).get::<Angle>("angle");
+            update_sky(ctx, ra, de);
+        }
     }
 
     fn update(&mut self, _: &mut Registry, ctx: &mut Context) {
@@ -54,19 +147,30 @@ impl State for RotationViewState {
 
         self.should_check_validity = false;
 
-        if self.button_pressed {
-            let ra = *ctx.get_widget(self.right_asc_input).get::<Angle>("angle");
-            let de = *ctx.get_widget(self.decl_input).get::<Angle>("angle");
-            ctx.push_event(UserEvent::Rotate(ra, de));
+        let ra = *ctx.get_widget(self.right_asc_input).get::<Angle>("angle");
+        let de = *ctx.get_widget(self.decl_input).get::<Angle>("angle");
+        update_sky(ctx, ra, de);
+
+        if self.button_pressed || self.sync_pressed {
+            ctx.push_event(if self.sync_pressed { UserEvent::Sync(ra, de) } else { UserEvent::Rotate(ra, de) });
         }
         self.button_pressed = false;
+        self.sync_pressed = false;
     }
 }
 
 
 widget!(RotationView<RotationViewState> {
     valid: bool, 
-    btn_text: String16
+    btn_text: String16,
+
+    /// longitude and latitude of the site, set by the MainView once validated in the PositionView
+    site: Option<(Angle, Angle)>,
+
+    // automatically set
+    sidereal_text: String16,
+    hour_angle_text: String16,
+    horizontal_text: String16
 });
 
 
@@ -102,7 +206,8 @@ impl Template for RotationView {
                     .child(
                         AngleView::new()
                             .id(RIGHT_ASC)
-                            .first_angle(false)
+                            .kind(AngleKind::RightAscension)
+                            .precision(2)
                             .on_changed_filter(vec!["valid"])
                             .on_changed(move |states, _, _| {
                                 state(id, states).check_validity();
@@ -119,7 +224,8 @@ impl Template for RotationView {
                     ).child(
                         AngleView::new()
                             .id(DECL)
-                            .first_angle(true)
+                            .kind(AngleKind::Declination)
+                            .precision(1)
                             .on_changed_filter(vec!["valid"])
                             .on_changed(move |states, _, _| {
                                 state(id, states).check_validity();
@@ -127,15 +233,33 @@ impl Template for RotationView {
 
                             .build(ctx)
                     ).build(ctx)
-            ).child(
-                Button::new()
-                    .text(("btn_text", id))
-                    .enabled(("valid", id))
-                    .on_click(move |states, _| {
-                        state(id, states).click();
-                        true
-                    })
-                    .build(ctx)
+            )
+            // whether the target is above the horizon, before slewing to it
+            .child(TextBlock::new().text(("sidereal_text", id)).build(ctx))
+            .child(TextBlock::new().text(("hour_angle_text", id)).build(ctx))
+            .child(TextBlock::new().text(("horizontal_text", id)).build(ctx))
+            .child(
+                Stack::new().orientation("horizontal").spacing(4.0)
+                    .child(
+                        Button::new()
+                            .text(("btn_text", id))
+                            .enabled(("valid", id))
+                            .on_click(move |states, _| {
+                                state(id, states).click();
+                                true
+                            })
+                            .build(ctx)
+                    ).child(
+                        // once a known star is centered with the pad
+                        Button::new()
+                            .text("Sync")
+                            .enabled(("valid", id))
+                            .on_click(move |states, _| {
+                                state(id, states).click_sync();
+                                true
+                            })
+                            .build(ctx)
+                    ).build(ctx)
             ).build(ctx)
         )
     }
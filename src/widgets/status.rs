@@ -0,0 +1,92 @@
+use orbtk::prelude::*;
+
+use crate::data::{Angle, MountStatus};
+
+
+static TRACKING_ON: &str = "actif";
+static TRACKING_OFF: &str = "inactif";
+
+static SLEWING_ON: &str = "en cours";
+static SLEWING_OFF: &str = "arrêté";
+
+
+#[derive(Default, AsAny)]
+pub struct StatusViewState {}
+
+fn format_angle(angle: Angle, first_symbol: &str, precision: usize) -> String {
+    let angle = angle.round(precision);
+    let sign = if angle.is_negative() { "-" } else { "" };
+    format!("{}{}{} {}' {:.*}\"", sign, angle.1, first_symbol, angle.2, precision, angle.3)
+}
+
+impl State for StatusViewState {
+    fn update(&mut self, _: &mut Registry, ctx: &mut Context) {
+        let status = *ctx.widget().get::<MountStatus>("status");
+        let precision = *ctx.widget().get::<usize>("precision");
+
+        status_view(ctx.widget()).set_right_asc_text(format_angle(status.right_asc, "h", precision));
+        status_view(ctx.widget()).set_decl_text(format_angle(status.decl, "°", precision));
+        status_view(ctx.widget()).set_altitude_text(format_angle(status.altitude, "°", precision));
+        status_view(ctx.widget()).set_azimuth_text(format_angle(status.azimuth, "°", precision));
+        status_view(ctx.widget()).set_tracking_text(if status.tracking { TRACKING_ON } else { TRACKING_OFF });
+        status_view(ctx.widget()).set_slewing_text(if status.slewing { SLEWING_ON } else { SLEWING_OFF });
+    }
+}
+
+
+widget!(StatusView<StatusViewState> {
+    /// last status reported by the backend
+    status: MountStatus,
+
+    /// number of decimals of the seconds
+    precision: usize,
+
+    // automatically set
+    right_asc_text: String16,
+    decl_text: String16,
+    altitude_text: String16,
+    azimuth_text: String16,
+    tracking_text: String16,
+    slewing_text: String16,
+
+    /// last command sent to the backend and its outcome, set by the MainView
+    command_text: String16
+});
+
+
+/// Generate a line "label value"
+fn generate_line(ctx: &mut BuildContext, id: Entity, label: &str, key: &str) -> Entity {
+    Stack::new().orientation("horizontal")
+        .child(
+            TextBlock::new()
+                .text(label)
+                .build(ctx)
+        ).child(
+            TextBlock::new()
+                .text((key, id))
+                .build(ctx)
+        ).build(ctx)
+}
+
+impl Template for StatusView {
+    fn template(self, id: Entity, ctx: &mut BuildContext) -> Self {
+        self.name("StatusView")
+            .tracking_text(TRACKING_OFF)
+            .slewing_text(SLEWING_OFF)
+            .child(Stack::new().spacing(10.0).child(
+                TextBlock::new()
+                    .text("État du téléscope")
+                    .font_size(25)
+                    .build(ctx)
+            )
+            .child(generate_line(ctx, id, "Ascension droite : ", "right_asc_text"))
+            .child(generate_line(ctx, id, "Déclinaison : ", "decl_text"))
+            .child(generate_line(ctx, id, "Altitude : ", "altitude_text"))
+            .child(generate_line(ctx, id, "Azimut : ", "azimuth_text"))
+            .child(generate_line(ctx, id, "Suivi : ", "tracking_text"))
+            .child(generate_line(ctx, id, "Pointage : ", "slewing_text"))
+            .child(generate_line(ctx, id, "Commande : ", "command_text"))
+            .build(ctx)
+        )
+    }
+}
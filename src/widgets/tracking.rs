@@ -0,0 +1,173 @@
+use orbtk::prelude::*;
+
+use crate::{
+    widgets::NumericTextBox,
+    events::{UserEvent, UserEventHandler},
+    data::{TrackingMode, MountStatus}
+};
+
+
+static ID_CUSTOM_RATE: &str = "tracking_view_custom_rate";
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Action {
+    /// starts or stops the tracking, in the last mode
+    Toggle,
+    /// tracks in the given mode
+    Mode(TrackingMode),
+    /// tracks at the rate of the text box
+    Custom
+}
+
+#[derive(Default, AsAny)]
+pub struct TrackingViewState {
+    action: Option<Action>
+}
+
+impl TrackingViewState {
+    fn action(&mut self, action: Action) {
+        self.action = Some(action);
+    }
+}
+
+impl State for TrackingViewState {
+    fn update(&mut self, _: &mut Registry, ctx: &mut Context) {
+        let status = *ctx.widget().get::<MountStatus>("status");
+
+        // the mode reported by the backend wins over the last one selected
+        if let Some(mode) = status.tracking_mode {
+            ctx.widget().set::<TrackingMode>("mode", mode);
+        }
+        let mode = *ctx.widget().get::<TrackingMode>("mode");
+
+        let event = match self.action.take() {
+            Some(Action::Toggle) if status.tracking => Some(UserEvent::Track(None)),
+            Some(Action::Toggle) => Some(UserEvent::Track(Some(mode))),
+            Some(Action::Mode(mode)) => Some(UserEvent::Track(Some(mode))),
+            Some(Action::Custom) => {
+                let rate = *ctx.child(ID_CUSTOM_RATE).get::<f64>("value");
+                Some(UserEvent::Track(Some(TrackingMode::Custom(rate))))
+            },
+            None => None
+        };
+        if let Some(UserEvent::Track(Some(mode))) = event {
+            ctx.widget().set::<TrackingMode>("mode", mode);
+        }
+        if let Some(event) = event {
+            ctx.push_event(event);
+        }
+
+        let mode = *ctx.widget().get::<TrackingMode>("mode");
+        ctx.widget().set::<String16>("toggle_text", String16::from(
+            if status.tracking { "Suivi : actif" } else { "Suivi : inactif" }
+        ));
+        ctx.widget().set::<String16>("mode_text", String16::from(format!("Mode : {}", mode_label(mode))));
+    }
+}
+
+fn mode_label(mode: TrackingMode) -> String {
+    match mode {
+        TrackingMode::Sidereal => "sidéral".to_string(),
+        TrackingMode::Lunar => "lunaire".to_string(),
+        TrackingMode::Solar => "solaire".to_string(),
+        TrackingMode::King => "King".to_string(),
+        TrackingMode::Custom(rate) => format!("{}″/s", rate)
+    }
+}
+
+
+widget!(TrackingView<TrackingViewState> {
+    /// last status reported by the backend, set by the MainView
+    status: MountStatus,
+
+    /// mode used when the tracking is turned on
+    mode: TrackingMode,
+
+    // automatically set
+    toggle_text: String16,
+    mode_text: String16
+});
+
+
+impl TrackingView {
+    pub fn on_user_event<H: Fn(&mut StatesContext, &UserEvent) -> bool + 'static>(
+        self,
+        handler: H,
+    ) -> Self {
+        self.insert_handler(UserEventHandler {
+            handler: Rc::new(handler),
+        })
+    }
+}
+
+
+fn generate_mode_button(ctx: &mut BuildContext, id: Entity, text: &str, action: Action) -> Entity {
+    Button::new()
+        .text(text)
+        .on_click(move |states, _| {
+            state(id, states).action(action);
+            true
+        })
+        .margin((0, 0, 4, 0))
+        .build(ctx)
+}
+
+impl Template for TrackingView {
+    fn template(self, id: Entity, ctx: &mut BuildContext) -> Self {
+        self.name("TrackingView")
+            .toggle_text("Suivi : inactif")
+            .mode_text("Mode : sidéral")
+            .child(Stack::new().spacing(10.0).child(
+                TextBlock::new()
+                    .text("Suivi")
+                    .font_size(25)
+                    .build(ctx)
+            ).child(
+                Button::new()
+                    .text(("toggle_text", id))
+                    .on_click(move |states, _| {
+                        state(id, states).action(Action::Toggle);
+                        true
+                    })
+                    .build(ctx)
+            )
+            .child(TextBlock::new().text(("mode_text", id)).build(ctx))
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_mode_button(ctx, id, "Sidéral", Action::Mode(TrackingMode::Sidereal)))
+                    .child(generate_mode_button(ctx, id, "Lunaire", Action::Mode(TrackingMode::Lunar)))
+                    .build(ctx)
+            )
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(generate_mode_button(ctx, id, "Solaire", Action::Mode(TrackingMode::Solar)))
+                    .child(generate_mode_button(ctx, id, "King", Action::Mode(TrackingMode::King)))
+                    .build(ctx)
+            )
+            .child(
+                Stack::new().orientation("horizontal")
+                    .child(
+                        NumericTextBox::new()
+                            .id(ID_CUSTOM_RATE)
+                            .float_mode(true)
+                            .min(0.0)
+                            .max(100.0)
+                            .step(0.1)
+                            .precision(3)
+                            .suffix("″/s")
+                            .margin((0, 0, 4, 0))
+                            .build(ctx)
+                    )
+                    .child(generate_mode_button(ctx, id, "Personnalisé", Action::Custom))
+                    .build(ctx)
+            )
+            .build(ctx)
+        )
+    }
+}
+
+// helper to request TrackingViewState
+fn state<'a>(id: Entity, states: &'a mut StatesContext) -> &'a mut TrackingViewState {
+    states.get_mut(id)
+}
@@ -0,0 +1,253 @@
+//! Encodings used to exchange messages (`Command`, `BackendEvent`, ...) with a
+//! backend running in another process.
+//!
+//! Every message is wrapped with the version of the schema it was encoded with:
+//! - JSON: `{"version": 1, "payload": ...}`, enums being externally tagged
+//!   (e.g. `{"Rotate": [["Positive", 12, 30, 0.0], ["Negative", 45, 0, 12.5]]}`)
+//! - binary: the version as a little endian `u16`, followed by the payload
+//!   encoded with bincode (little endian, variable length integers)
+//!
+//! The version is bumped whenever the layout of a message changes.
+
+use std::{error, fmt};
+
+use bincode::Options;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+
+/// Version of the layout of the messages
+pub const SCHEMA_VERSION: u16 = 9;
+
+
+#[derive(Debug)]
+pub enum WireError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    /// The message was encoded with another version of the schema
+    Version(u16),
+    /// The message is too short to contain the version
+    Truncated
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WireError::Json(e) => write!(f, "invalid JSON message: {}", e),
+            WireError::Binary(e) => write!(f, "invalid binary message: {}", e),
+            WireError::Version(v) => write!(f, "unsupported schema version {} (expected {})", v, SCHEMA_VERSION),
+            WireError::Truncated => write!(f, "truncated message")
+        }
+    }
+}
+
+impl error::Error for WireError {}
+
+impl From<serde_json::Error> for WireError {
+    fn from(e: serde_json::Error) -> Self {
+        WireError::Json(e)
+    }
+}
+
+impl From<bincode::Error> for WireError {
+    fn from(e: bincode::Error) -> Self {
+        WireError::Binary(e)
+    }
+}
+
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u16,
+    payload: &'a T
+}
+
+// read first, so that a version mismatch is not reported as a malformed payload
+#[derive(Deserialize)]
+struct Header {
+    version: u16
+}
+
+#[derive(Deserialize)]
+struct OwnedEnvelope<T> {
+    payload: T
+}
+
+fn check_version(version: u16) -> Result<(), WireError> {
+    if version == SCHEMA_VERSION {
+        Ok(())
+    } else {
+        Err(WireError::Version(version))
+    }
+}
+
+fn binary_options() -> impl Options {
+    bincode::DefaultOptions::new().with_little_endian().with_varint_encoding()
+}
+
+
+/// Encodes a message as JSON
+pub fn to_json<T: Serialize>(message: &T) -> Result<String, WireError> {
+    Ok(serde_json::to_string(&Envelope { version: SCHEMA_VERSION, payload: message })?)
+}
+
+/// Decodes a message encoded with `to_json`
+pub fn from_json<T: DeserializeOwned>(text: &str) -> Result<T, WireError> {
+    let header: Header = serde_json::from_str(text)?;
+    check_version(header.version)?;
+
+    let envelope: OwnedEnvelope<T> = serde_json::from_str(text)?;
+    Ok(envelope.payload)
+}
+
+/// Encodes a message in the compact binary format
+pub fn to_bytes<T: Serialize>(message: &T) -> Result<Vec<u8>, WireError> {
+    let mut bytes = SCHEMA_VERSION.to_le_bytes().to_vec();
+    binary_options().serialize_into(&mut bytes, message)?;
+    Ok(bytes)
+}
+
+/// Decodes a message encoded with `to_bytes`
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, WireError> {
+    if bytes.len() < 2 {
+        return Err(WireError::Truncated);
+    }
+    check_version(u16::from_le_bytes([bytes[0], bytes[1]]))?;
+
+    Ok(binary_options().deserialize(&bytes[2..])?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{Angle, Sign, Direction, SlewRate, TrackingMode, ParkPosition, MountStatus},
+        events::{UserEvent, Command, BackendEvent}
+    };
+
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + fmt::Debug>(message: T) {
+        assert_eq!(from_json::<T>(&to_json(&message).unwrap()).unwrap(), message);
+        assert_eq!(from_bytes::<T>(&to_bytes(&message).unwrap()).unwrap(), message);
+    }
+
+    #[test]
+    fn user_events() {
+        let right_asc = Angle(Sign::Positive, 12, 30, 15.25);
+        let decl = Angle(Sign::Negative, 5, 12, 33.5);
+        let events = [
+            UserEvent::Zero,
+            UserEvent::Rotate(right_asc, decl),
+            UserEvent::Sync(right_asc, decl),
+            UserEvent::BeginMove(Direction::Up, SlewRate::Guide),
+            UserEvent::BeginMove(Direction::Left, SlewRate::Sidereal(16.5)),
+            UserEvent::EndMove(Direction::Right),
+            UserEvent::KeepMoving,
+            UserEvent::Abort,
+            UserEvent::Track(None),
+            UserEvent::Track(Some(TrackingMode::King)),
+            UserEvent::Track(Some(TrackingMode::Custom(15.5))),
+            UserEvent::Park(ParkPosition { altitude: Angle::from_decimal(90.0), azimuth: decl }),
+            UserEvent::Unpark,
+            UserEvent::FindHome,
+            UserEvent::Position(decl, right_asc)
+        ];
+        for event in events.iter() {
+            round_trip(*event);
+            round_trip(Command::new(*event));
+        }
+    }
+
+    /// Checks the exact encodings of `message`, the bytes being written in hexadecimal
+    fn golden<T: Serialize + DeserializeOwned + PartialEq + fmt::Debug>(message: T, json: &str, bytes: &str) {
+        assert_eq!(to_json(&message).unwrap(), json);
+        let encoded: String = to_bytes(&message).unwrap().iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(encoded, bytes, "binary encoding of {:?}", message);
+        assert_eq!(from_json::<T>(json).unwrap(), message);
+    }
+
+    /// The layout other processes rely on: a change which fails here needs a bump of SCHEMA_VERSION
+    #[test]
+    fn stable_layout() {
+        let right_asc = Angle(Sign::Positive, 12, 30, 15.25);
+        let decl = Angle(Sign::Negative, 5, 12, 33.5);
+
+        golden(UserEvent::Zero, r#"{"version":9,"payload":"Zero"}"#, "090000");
+        golden(UserEvent::Rotate(right_asc, decl),
+            r#"{"version":9,"payload":{"Rotate":[["Positive",12,30,15.25],["Negative",5,12,33.5]]}}"#,
+            "090001000c1e0000000000802e4001050c0000000000c04040");
+        golden(UserEvent::Sync(right_asc, decl),
+            r#"{"version":9,"payload":{"Sync":[["Positive",12,30,15.25],["Negative",5,12,33.5]]}}"#,
+            "090002000c1e0000000000802e4001050c0000000000c04040");
+        golden(UserEvent::BeginMove(Direction::Up, SlewRate::Guide),
+            r#"{"version":9,"payload":{"BeginMove":["Up","Guide"]}}"#, "0900030000");
+        golden(UserEvent::BeginMove(Direction::Left, SlewRate::Sidereal(16.5)),
+            r#"{"version":9,"payload":{"BeginMove":["Left",{"Sidereal":16.5}]}}"#, "09000302040000000000803040");
+        golden(UserEvent::EndMove(Direction::Right), r#"{"version":9,"payload":{"EndMove":"Right"}}"#, "09000403");
+        golden(UserEvent::KeepMoving, r#"{"version":9,"payload":"KeepMoving"}"#, "090005");
+        golden(UserEvent::Abort, r#"{"version":9,"payload":"Abort"}"#, "090006");
+        golden(UserEvent::Track(None), r#"{"version":9,"payload":{"Track":null}}"#, "09000700");
+        golden(UserEvent::Track(Some(TrackingMode::King)), r#"{"version":9,"payload":{"Track":"King"}}"#, "0900070103");
+        golden(UserEvent::Track(Some(TrackingMode::Custom(15.5))),
+            r#"{"version":9,"payload":{"Track":{"Custom":15.5}}}"#, "09000701040000000000002f40");
+        golden(UserEvent::Park(ParkPosition { altitude: Angle(Sign::Positive, 90, 0, 0.0), azimuth: decl }),
+            r#"{"version":9,"payload":{"Park":{"altitude":["Positive",90,0,0.0],"azimuth":["Negative",5,12,33.5]}}}"#,
+            "090008005a00000000000000000001050c0000000000c04040");
+        golden(UserEvent::Unpark, r#"{"version":9,"payload":"Unpark"}"#, "090009");
+        golden(UserEvent::FindHome, r#"{"version":9,"payload":"FindHome"}"#, "09000a");
+        golden(UserEvent::Position(decl, right_asc),
+            r#"{"version":9,"payload":{"Position":[["Negative",5,12,33.5],["Positive",12,30,15.25]]}}"#,
+            "09000b01050c0000000000c04040000c1e0000000000802e40");
+
+        golden(Command { seq: 300, event: UserEvent::Abort }, r#"{"version":9,"payload":{"seq":300,"event":"Abort"}}"#, "0900fb2c0106");
+
+        golden(BackendEvent::Accepted(7), r#"{"version":9,"payload":{"Accepted":7}}"#, "09000107");
+        golden(BackendEvent::Rejected(70000), r#"{"version":9,"payload":{"Rejected":70000}}"#, "090002fc70110100");
+        golden(
+            BackendEvent::Status(MountStatus {
+                right_asc,
+                decl,
+                altitude: Angle(Sign::Positive, 45, 0, 0.5),
+                azimuth: Angle(Sign::Positive, 180, 15, 0.0),
+                tracking: true,
+                tracking_mode: Some(TrackingMode::Sidereal),
+                slewing: false,
+                parked: true
+            }),
+            concat!(
+                r#"{"version":9,"payload":{"Status":{"right_asc":["Positive",12,30,15.25],"decl":["Negative",5,12,33.5],"#,
+                r#""altitude":["Positive",45,0,0.5],"azimuth":["Positive",180,15,0.0],"#,
+                r#""tracking":true,"tracking_mode":"Sidereal","slewing":false,"parked":true}}}"#
+            ),
+            "090000000c1e0000000000802e4001050c0000000000c04040002d00000000000000e03f00b40f00000000000000000101000001"
+        );
+    }
+
+    #[test]
+    fn angles() {
+        round_trip(Angle(Sign::Negative, 0, 30, 0.0));
+        round_trip(Angle(Sign::Negative, 89, 59, 59.999));
+        round_trip(Angle(Sign::Positive, 23, 0, 0.125));
+    }
+
+    #[test]
+    fn directions() {
+        for direction in [Direction::Up, Direction::Down, Direction::Left, Direction::Right].iter() {
+            round_trip(*direction);
+        }
+    }
+
+    #[test]
+    fn wrong_version() {
+        let version = SCHEMA_VERSION + 1;
+
+        let text = to_json(&Direction::Up).unwrap().replace(
+            &format!("\"version\":{}", SCHEMA_VERSION), &format!("\"version\":{}", version)
+        );
+        assert!(matches!(from_json::<Direction>(&text), Err(WireError::Version(v)) if v == version));
+
+        let mut bytes = to_bytes(&Direction::Up).unwrap();
+        bytes[..2].copy_from_slice(&version.to_le_bytes());
+        assert!(matches!(from_bytes::<Direction>(&bytes), Err(WireError::Version(v)) if v == version));
+        assert!(matches!(from_bytes::<Direction>(&bytes[..1]), Err(WireError::Truncated)));
+    }
+}